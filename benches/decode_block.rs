@@ -0,0 +1,269 @@
+//! Decoding cost of block envelopes.
+//!
+//! By default a synthetic block shaped like a typical mainnet block is used: about 1,400
+//! transactions, two thirds of them votes, the rest v0 transactions with inner instructions,
+//! token balances and program logs. Set `BENCH_BLOCK_FILE` to a file holding one envelope or a
+//! getBlock response to measure a real block instead.
+
+use {
+    criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput},
+    ingestor_kafka_hdfs::{
+        block_envelope::{decode_envelope_str, EnvelopePayload},
+        block_processor::{convert_encoded_block, BlockConversionConfig},
+        format_parser::{FormatParser, NdJsonParser},
+        schema_compat::SchemaCompat,
+    },
+    serde_json::{json, Value},
+};
+
+const VOTE_TRANSACTIONS: usize = 950;
+const OTHER_TRANSACTIONS: usize = 450;
+const ENTRIES: usize = 64;
+
+const VOTE_PROGRAM: &str = "Vote111111111111111111111111111111111111111";
+const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const COMPUTE_BUDGET_PROGRAM: &str = "ComputeBudget111111111111111111111111111111";
+
+fn key(seed: usize, salt: u8) -> String {
+    let mut bytes = [salt; 32];
+    bytes[..8].copy_from_slice(&(seed as u64).to_le_bytes());
+    bs58::encode(bytes).into_string()
+}
+
+fn signature(seed: usize) -> String {
+    let mut bytes = [0xa5; 64];
+    bytes[..8].copy_from_slice(&(seed as u64).to_le_bytes());
+    bs58::encode(bytes).into_string()
+}
+
+fn instruction_data(seed: usize, len: usize) -> String {
+    let bytes: Vec<u8> = (0..len).map(|i| (seed + i * 31) as u8).collect();
+    bs58::encode(bytes).into_string()
+}
+
+fn vote_transaction(seed: usize, recent_blockhash: &str) -> Value {
+    json!({
+        "transaction": {
+            "signatures": [signature(seed)],
+            "message": {
+                "header": {
+                    "numRequiredSignatures": 1,
+                    "numReadonlySignedAccounts": 0,
+                    "numReadonlyUnsignedAccounts": 1
+                },
+                "accountKeys": [key(seed, 1), key(seed, 2), VOTE_PROGRAM],
+                "recentBlockhash": recent_blockhash,
+                "instructions": [{
+                    "programIdIndex": 2,
+                    "accounts": [1, 0],
+                    "data": instruction_data(seed, 110),
+                    "stackHeight": null
+                }]
+            }
+        },
+        "meta": {
+            "err": null,
+            "status": { "Ok": null },
+            "fee": 5000,
+            "preBalances": [1_500_000_000u64 + seed as u64, 27_074_400, 1],
+            "postBalances": [1_499_995_000u64 + seed as u64, 27_074_400, 1],
+            "innerInstructions": [],
+            "logMessages": [
+                format!("Program {VOTE_PROGRAM} invoke [1]"),
+                format!("Program {VOTE_PROGRAM} success")
+            ],
+            "preTokenBalances": [],
+            "postTokenBalances": [],
+            "rewards": [],
+            "loadedAddresses": { "writable": [], "readonly": [] },
+            "computeUnitsConsumed": 2100
+        },
+        "version": "legacy"
+    })
+}
+
+fn token_balance(account_index: u8, seed: usize, amount: u64) -> Value {
+    json!({
+        "accountIndex": account_index,
+        "mint": key(seed % 97, 7),
+        "owner": key(seed, 8),
+        "programId": TOKEN_PROGRAM,
+        "uiTokenAmount": {
+            "amount": amount.to_string(),
+            "decimals": 6,
+            "uiAmount": amount as f64 / 1e6,
+            "uiAmountString": format!("{}", amount as f64 / 1e6)
+        }
+    })
+}
+
+fn other_transaction(seed: usize, recent_blockhash: &str) -> Value {
+    let static_keys: Vec<String> = (0..9).map(|i| key(seed, 10 + i)).collect();
+    let mut account_keys = static_keys;
+    account_keys.push(COMPUTE_BUDGET_PROGRAM.to_string());
+    account_keys.push(TOKEN_PROGRAM.to_string());
+    let program_index = account_keys.len() - 1;
+
+    let logs: Vec<String> = (0..24)
+        .map(|i| match i % 4 {
+            0 => format!("Program {} invoke [{}]", key(seed % 13, 30), 1 + i % 3),
+            1 => "Program log: Instruction: Swap".to_string(),
+            2 => format!(
+                "Program {} consumed {} of 200000 compute units",
+                key(seed % 13, 30),
+                1000 * i
+            ),
+            _ => format!("Program {} success", key(seed % 13, 30)),
+        })
+        .collect();
+
+    json!({
+        "transaction": {
+            "signatures": [signature(1_000_000 + seed)],
+            "message": {
+                "header": {
+                    "numRequiredSignatures": 1,
+                    "numReadonlySignedAccounts": 0,
+                    "numReadonlyUnsignedAccounts": 3
+                },
+                "accountKeys": account_keys,
+                "recentBlockhash": recent_blockhash,
+                "instructions": [
+                    { "programIdIndex": 9, "accounts": [], "data": "3DTZbgwsozUF", "stackHeight": null },
+                    { "programIdIndex": 9, "accounts": [], "data": "Fj2Eoy", "stackHeight": null },
+                    {
+                        "programIdIndex": program_index,
+                        "accounts": [0, 1, 2, 3, 4, 5, 6, 7, 8, 11, 12, 13],
+                        "data": instruction_data(seed, 40),
+                        "stackHeight": null
+                    }
+                ],
+                "addressTableLookups": [{
+                    "accountKey": key(seed % 29, 40),
+                    "writableIndexes": [3, 17],
+                    "readonlyIndexes": [42]
+                }]
+            }
+        },
+        "meta": {
+            "err": null,
+            "status": { "Ok": null },
+            "fee": 12_000,
+            "preBalances": (0..14).map(|i| 2_039_280u64 * (i + 1)).collect::<Vec<_>>(),
+            "postBalances": (0..14).map(|i| 2_039_280u64 * (i + 1) - 7).collect::<Vec<_>>(),
+            "innerInstructions": [{
+                "index": 2,
+                "instructions": (0..6).map(|i| json!({
+                    "programIdIndex": 10,
+                    "accounts": [i % 9, (i + 1) % 9, 0],
+                    "data": instruction_data(seed + i, 9),
+                    "stackHeight": 2
+                })).collect::<Vec<_>>()
+            }],
+            "logMessages": logs,
+            "preTokenBalances": (0..4).map(|i| token_balance(i + 1, seed, 1_000_000)).collect::<Vec<_>>(),
+            "postTokenBalances": (0..4).map(|i| token_balance(i + 1, seed, 990_000)).collect::<Vec<_>>(),
+            "rewards": [],
+            "loadedAddresses": {
+                "writable": [key(seed, 50), key(seed, 51)],
+                "readonly": [key(seed, 52)]
+            },
+            "computeUnitsConsumed": 94_512
+        },
+        "version": 0
+    })
+}
+
+fn mainnet_like_envelope() -> String {
+    let slot = 300_000_000usize;
+    let recent_blockhash = key(slot, 99);
+    let transactions: Vec<Value> = (0..VOTE_TRANSACTIONS)
+        .map(|i| vote_transaction(i, &recent_blockhash))
+        .chain((0..OTHER_TRANSACTIONS).map(|i| other_transaction(i, &recent_blockhash)))
+        .collect();
+    let entries: Vec<Value> = (0..ENTRIES)
+        .map(|i| {
+            json!({
+                "numHashes": 12_500,
+                "hash": key(i, 60),
+                "numTransactions": (VOTE_TRANSACTIONS + OTHER_TRANSACTIONS) / ENTRIES,
+                "startingTransactionIndex": i * ((VOTE_TRANSACTIONS + OTHER_TRANSACTIONS) / ENTRIES)
+            })
+        })
+        .collect();
+
+    json!({
+        "blockID": slot,
+        "previousBlockhash": key(slot - 1, 98),
+        "blockhash": key(slot, 98),
+        "parentSlot": slot - 1,
+        "transactions": transactions,
+        "rewards": [{
+            "pubkey": key(slot, 70),
+            "lamports": 12_345_678,
+            "postBalance": 987_654_321_000u64,
+            "rewardType": "Fee",
+            "commission": null
+        }],
+        "blockTime": 1_730_000_000,
+        "blockHeight": 280_000_000,
+        "entries": entries
+    })
+    .to_string()
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let envelope = match std::env::var("BENCH_BLOCK_FILE") {
+        Ok(path) => std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read {path}: {e}"))
+            .trim()
+            .to_string(),
+        Err(_) => mainnet_like_envelope(),
+    };
+    let compat = SchemaCompat::default();
+    match decode_envelope_str(&mut envelope.clone(), &compat) {
+        Ok(Some(EnvelopePayload::Block(_))) => {}
+        Ok(_) => panic!("Benchmark input is not a single block envelope"),
+        Err(e) => panic!("Benchmark input does not decode: {e:#}"),
+    }
+
+    let mut group = c.benchmark_group("decode_block");
+    group.throughput(Throughput::Bytes(envelope.len() as u64));
+    group.sample_size(20);
+
+    // Envelopes are parsed in place, so each iteration gets its own copy of the text
+    group.bench_function("envelope_from_str", |b| {
+        b.iter_batched(
+            || envelope.clone(),
+            |mut envelope| decode_envelope_str(black_box(&mut envelope), &compat).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("ndjson_record", |b| {
+        let parser = NdJsonParser::default();
+        b.iter_batched(
+            || envelope.clone(),
+            |mut envelope| parser.parse_record(black_box(&mut envelope)).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("decode_and_convert", |b| {
+        let config = BlockConversionConfig::default();
+        b.iter_batched(
+            || envelope.clone(),
+            |mut envelope| {
+                let Some(EnvelopePayload::Block(envelope)) =
+                    decode_envelope_str(black_box(&mut envelope), &compat).unwrap()
+                else {
+                    unreachable!()
+                };
+                convert_encoded_block(envelope.block_id, envelope.block, &config).unwrap()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
@@ -23,6 +23,8 @@ echo "create 'tx', 'x'" | /opt/hbase/bin/hbase shell || echo "INFO: Table 'tx' a
 echo "create 'tx-by-addr', 'x'" | /opt/hbase/bin/hbase shell || echo "INFO: Table 'tx-by-addr' already exists, skipping."
 echo "create 'tx_full', 'x'" | /opt/hbase/bin/hbase shell || echo "INFO: Table 'tx_full' already exists, skipping."
 echo "create 'ingestor_indexing_progress', 'x'" | /opt/hbase/bin/hbase shell || echo "INFO: Table 'ingestor_indexing_progress' already exists, skipping."
+echo "create 'ingestor_file_registry', 'x'" | /opt/hbase/bin/hbase shell || echo "INFO: Table 'ingestor_file_registry' already exists, skipping."
+echo "create 'quarantined_blocks', 'x'" | /opt/hbase/bin/hbase shell || echo "INFO: Table 'quarantined_blocks' already exists, skipping."
 
 echo "HBase table creation completed."
 exit 0
\ No newline at end of file
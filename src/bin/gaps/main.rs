@@ -0,0 +1,60 @@
+use {
+    anyhow::Result,
+    ingestor_kafka_hdfs::{
+        cli::{gap_detector_app, process_gap_detector_arguments, process_missing_slots_topic},
+        config::Config,
+        gap_detector::{publish_missing_slots, GapDetector},
+        ledger_storage::{LedgerStorage, LedgerStorageConfig},
+        queue_producer::KafkaQueueProducer,
+    },
+    log::info,
+    serde_json::json,
+};
+
+const SERVICE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Missing slot messages are small JSON objects
+const MESSAGE_MAX_BYTES: u32 = 1024 * 1024;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli_app = gap_detector_app(SERVICE_VERSION);
+    let matches = cli_app.get_matches();
+
+    env_logger::init();
+    info!("Starting the Solana block gap detector (Version: {SERVICE_VERSION})");
+
+    let gap_detector_config = process_gap_detector_arguments(&matches);
+    let missing_slots_topic = process_missing_slots_topic(&matches);
+
+    let config = Config::new();
+
+    let ledger_storage = LedgerStorage::new_with_config(LedgerStorageConfig {
+        address: config.hbase_address.clone(),
+        namespace: config.namespace.clone(),
+        ..LedgerStorageConfig::default()
+    })
+    .await;
+
+    let mut report = GapDetector::new(ledger_storage, gap_detector_config)
+        .detect()
+        .await?;
+
+    // One line per missing slot, followed by the summary
+    for missing_slot in &report.missing {
+        println!("{}", serde_json::to_string(missing_slot)?);
+    }
+
+    if let Some(topic) = missing_slots_topic {
+        let producer = KafkaQueueProducer::new(&config.kafka_brokers, &topic, MESSAGE_MAX_BYTES)?;
+        report.summary.published = publish_missing_slots(&producer, &report.missing).await?;
+        info!(
+            "Published {} missing slots to {topic}",
+            report.summary.published
+        );
+    }
+
+    println!("{}", json!({ "summary": report.summary }));
+
+    Ok(())
+}
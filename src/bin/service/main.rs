@@ -1,28 +1,43 @@
 use {
     anyhow::{Context, Result},
-    clap::ArgMatches,
+    clap::{value_t_or_exit, ArgMatches},
     hdfs_native::Client,
     ingestor_kafka_hdfs::{
         block_processor::{BlockProcessor, BlockProcessorTrait},
-        cli::{block_uploader_app, process_cache_arguments, process_uploader_arguments},
+        cli::{
+            block_uploader_app, process_block_conversion_arguments, process_cache_arguments,
+            process_file_lifecycle_arguments, process_file_registry_arguments, process_max_decompressed_message_size,
+            process_sanity_rules_arguments, process_schema_compat_arguments,
+            process_signature_verification_arguments, process_uploader_arguments,
+        },
         config::Config,
         decompressor::{Decompressor, GzipDecompressor},
-        file_processor::FileProcessor,
+        file_processor::{FileProcessor, FileProcessorConfig, Processor},
+        file_splitter::FileSplitter,
+        file_registry::HBaseFileRegistry,
         file_storage::HdfsStorage,
         format_parser::{FormatParser, NdJsonParser},
+        hbase::HBaseConnection,
         ingestor::Ingestor,
         ledger_storage::{LedgerCacheConfig, LedgerStorage, LedgerStorageConfig, UploaderConfig},
-        message_decoder::{JsonMessageDecoder, MessageDecoder},
+        message_decoder::{
+            FilePathDecoder, JsonMessageDecoder, MessageDecoder, RoutingMessageDecoder,
+        },
+        protobuf_decoder::ProtobufBlockDecoder,
+        schema_registry::{ConfluentMessageDecoder, SchemaRegistryClient},
         queue_consumer::{KafkaConfig, KafkaQueueConsumer, QueueConsumer},
         queue_producer::KafkaQueueProducer,
     },
     log::info,
     rdkafka::config::RDKafkaLogLevel,
-    std::sync::Arc,
+    std::{path::PathBuf, sync::Arc, time::Duration},
 };
 
 const SERVICE_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// How often the sanity rule violation counts are logged.
+const SANITY_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli_app = block_uploader_app(SERVICE_VERSION);
@@ -34,12 +49,19 @@ async fn main() -> Result<()> {
         SERVICE_VERSION
     );
 
-    if matches.is_present("add_empty_tx_metadata_if_missing") {
-        std::env::set_var("ADD_EMPTY_TX_METADATA_IF_MISSING", "1");
-    }
-
     let uploader_config = process_uploader_arguments(&matches);
+    let conversion_config = process_block_conversion_arguments(&matches);
+    let schema_compat = process_schema_compat_arguments(&matches);
+    let sanity_rules = process_sanity_rules_arguments(&matches);
+    let signature_verifier = process_signature_verification_arguments(&matches)?;
     let cache_config = process_cache_arguments(&matches);
+    let file_processor_config = FileProcessorConfig {
+        lifecycle: process_file_lifecycle_arguments(&matches),
+        force_reprocess: matches.is_present("force_reprocess_files"),
+        verify_checksum_sidecars: matches.is_present("verify_checksum_sidecars"),
+        require_checksum: matches.is_present("require_checksum"),
+    };
+    let file_registry_table = process_file_registry_arguments(&matches);
 
     let config = Arc::new(Config::new());
 
@@ -47,8 +69,39 @@ async fn main() -> Result<()> {
     let file_storage = HdfsStorage::new(hdfs_client);
 
     let decompressor: Box<dyn Decompressor + Send + Sync> = Box::new(GzipDecompressor {});
-    let message_decoder: Arc<dyn MessageDecoder + Send + Sync> = Arc::new(JsonMessageDecoder {});
-    let format_parser: Arc<dyn FormatParser + Send + Sync> = Arc::new(NdJsonParser {});
+    let max_decompressed_message_size = process_max_decompressed_message_size(&matches);
+    let json_decoder: Arc<dyn MessageDecoder + Send + Sync> =
+        Arc::new(
+            JsonMessageDecoder::new(max_decompressed_message_size)
+                .with_schema_compat(schema_compat.clone()),
+        );
+    let protobuf_decoder: Arc<dyn MessageDecoder + Send + Sync> =
+        Arc::new(ProtobufBlockDecoder::new(max_decompressed_message_size));
+    let schema_registry = SchemaRegistryClient::new(
+        matches.value_of("schema_registry_url").map(str::to_string),
+        matches.value_of("schema_cache_dir").map(PathBuf::from),
+    );
+    let confluent_decoder: Arc<dyn MessageDecoder + Send + Sync> =
+        Arc::new(ConfluentMessageDecoder::new(
+            schema_registry,
+            json_decoder.clone(),
+            protobuf_decoder.clone(),
+        )
+        .with_schema_compat(schema_compat.clone()));
+    let fallback_decoder = match matches.value_of("message_format") {
+        Some("protobuf") => protobuf_decoder.clone(),
+        Some("confluent") => confluent_decoder.clone(),
+        _ => json_decoder.clone(),
+    };
+    let message_decoder: Arc<dyn MessageDecoder + Send + Sync> = Arc::new(
+        RoutingMessageDecoder::new(fallback_decoder)
+            .with_decoder("json", json_decoder)
+            .with_decoder("file-path", Arc::new(FilePathDecoder))
+            .with_decoder("protobuf-block", protobuf_decoder)
+            .with_decoder("confluent", confluent_decoder),
+    );
+    let format_parser: Arc<dyn FormatParser + Send + Sync> =
+        Arc::new(NdJsonParser::default().with_schema_compat(schema_compat));
 
     let ledger_storage_config = LedgerStorageConfig {
         address: config.hbase_address.clone(),
@@ -58,14 +111,34 @@ async fn main() -> Result<()> {
     };
     let ledger_storage = LedgerStorage::new_with_config(ledger_storage_config).await;
 
-    let block_processor: Box<dyn BlockProcessorTrait + Send + Sync> = Box::new(BlockProcessor::new(ledger_storage.clone()));
+    if sanity_rules.is_enabled() {
+        tokio::spawn(sanity_rules.clone().report_violations(SANITY_REPORT_INTERVAL));
+    }
 
-    let file_processor = Arc::new(FileProcessor::new(
-        file_storage,
+    let block_processor: Box<dyn BlockProcessorTrait + Send + Sync> = Box::new(
+        BlockProcessor::new(ledger_storage.clone())
+            .with_conversion_config(conversion_config)
+            .with_sanity_rules(sanity_rules)
+            .with_signature_verifier(signature_verifier),
+    );
+
+    let file_processor = FileProcessor::new_with_config(
+        file_storage.clone(),
         format_parser.clone(),
         block_processor,
         decompressor,
-    ));
+        file_processor_config,
+    );
+
+    let file_processor = match file_registry_table {
+        Some(table) => {
+            let connection =
+                HBaseConnection::new(&config.hbase_address, config.namespace.as_deref()).await;
+            file_processor.with_registry(Arc::new(HBaseFileRegistry::new(connection, &table)))
+        }
+        None => file_processor,
+    };
+    let file_processor: Arc<dyn Processor + Send + Sync> = Arc::new(file_processor);
 
     let kafka_config = KafkaConfig {
         group_id: config.kafka_group_id.clone(),
@@ -90,7 +163,24 @@ async fn main() -> Result<()> {
         message_max_bytes,
     )?;
 
-    let mut ingestor = Ingestor::new(consumer, kafka_producer, file_processor, message_decoder);
+    let processor: Arc<dyn Processor + Send + Sync> = if matches.is_present("split_files") {
+        let num_splits = value_t_or_exit!(matches, "split_files", u64);
+        let split_topic = matches
+            .value_of("split_topic")
+            .unwrap_or(&config.kafka_consume_topic);
+        let split_producer =
+            KafkaQueueProducer::new(&config.kafka_brokers, split_topic, message_max_bytes)?;
+        Arc::new(FileSplitter::new(
+            file_storage,
+            split_producer,
+            file_processor,
+            num_splits,
+        ))
+    } else {
+        file_processor
+    };
+
+    let mut ingestor = Ingestor::new(consumer, kafka_producer, processor, message_decoder);
 
     ingestor.run().await?;
 
@@ -3,20 +3,31 @@ use {
     hdfs_native::Client,
     ingestor_kafka_hdfs::{
         block_processor::{BlockProcessor, BlockProcessorTrait},
-        cli::{block_uploader_app, process_cache_arguments, process_uploader_arguments},
+        cli::{
+            block_uploader_app, process_block_conversion_arguments, process_cache_arguments,
+            process_file_lifecycle_arguments, process_file_registry_arguments, process_max_decompressed_message_size,
+            process_sanity_rules_arguments, process_schema_compat_arguments,
+            process_signature_verification_arguments, process_uploader_arguments,
+        },
         config::Config,
         decompressor::{Decompressor, GzipDecompressor},
-        file_processor::{FileProcessor, Processor},
+        file_processor::{FileProcessor, FileProcessorConfig, Processor},
+        file_registry::HBaseFileRegistry,
         file_storage::HdfsStorage,
         format_parser::{FormatParser, NdJsonParser},
+        hbase::HBaseConnection,
         ledger_storage::{LedgerStorage, LedgerStorageConfig},
         message_decoder::{JsonMessageDecoder, MessageDecoder},
+        queue_consumer::MessageHeaders,
     },
     log::info,
     std::sync::Arc,
     tokio::io::{AsyncBufReadExt, BufReader},
+    validate::{ReportFormat, Validator},
 };
 
+mod validate;
+
 const SERVICE_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[tokio::main]
@@ -30,57 +41,62 @@ async fn main() -> Result<()> {
         SERVICE_VERSION
     );
 
-    if matches.is_present("add_empty_tx_metadata_if_missing") {
-        std::env::set_var("ADD_EMPTY_TX_METADATA_IF_MISSING", "1");
-    }
-
     let uploader_config = process_uploader_arguments(&matches);
+    let conversion_config = process_block_conversion_arguments(&matches);
+    let schema_compat = process_schema_compat_arguments(&matches);
+    let sanity_rules = process_sanity_rules_arguments(&matches);
+    let signature_verifier = process_signature_verification_arguments(&matches)?;
     let cache_config = process_cache_arguments(&matches);
+    let file_processor_config = FileProcessorConfig {
+        lifecycle: process_file_lifecycle_arguments(&matches),
+        force_reprocess: matches.is_present("force_reprocess_files"),
+        verify_checksum_sidecars: matches.is_present("verify_checksum_sidecars"),
+        require_checksum: matches.is_present("require_checksum"),
+    };
+    let file_registry_table = process_file_registry_arguments(&matches);
     let validate_only = matches.is_present("validate_only");
+    let dry_run = matches.is_present("dry_run");
 
     let config = Arc::new(Config::new());
 
-    let decoder: std::sync::Arc<dyn MessageDecoder + Send + Sync> = std::sync::Arc::new(JsonMessageDecoder {});
+    let decoder: std::sync::Arc<dyn MessageDecoder + Send + Sync> = std::sync::Arc::new(
+        JsonMessageDecoder::new(process_max_decompressed_message_size(&matches))
+            .with_schema_compat(schema_compat.clone()),
+    );
 
-    // Read NDJSON lines from stdin and either validate-only or process normally
+    // Read NDJSON lines from stdin and either validate, dry run or process normally
     let reader = BufReader::new(tokio::io::stdin());
     let mut lines = reader.lines();
 
-    if validate_only {
+    if validate_only || dry_run {
+        let format = match matches.value_of("validate_report") {
+            Some("json") => ReportFormat::Json,
+            _ => ReportFormat::Text,
+        };
+        let dry_run_storage = LedgerStorage::new_dry_run(uploader_config.clone());
+        let mut validator = Validator::new(format, &conversion_config, &schema_compat)
+            .with_sanity_rules(&sanity_rules);
+        if dry_run {
+            validator = validator.with_dry_run(&dry_run_storage);
+        }
+        let mut line_number = 0;
         while let Some(line) = lines.next_line().await? {
+            line_number += 1;
             let trimmed = line.trim();
             if trimmed.is_empty() {
                 continue;
             }
-            match decoder.decode(trimmed.as_bytes()).await {
-                Ok(decoded) => {
-                    match decoded {
-                        ingestor_kafka_hdfs::message_decoder::DecodedPayload::Block(block_id, _) => {
-                            eprintln!("Parsed block (no entries): blockID={}", block_id);
-                        }
-                        ingestor_kafka_hdfs::message_decoder::DecodedPayload::BlockWithEntries(block_id, _, _) => {
-                            eprintln!("Parsed block with entries: blockID={}", block_id);
-                        }
-                        ingestor_kafka_hdfs::message_decoder::DecodedPayload::FilePath(path) => {
-                            eprintln!("Parsed file path payload (unexpected in validate-only): {}", path);
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to decode input: {}", e);
-                    for cause in e.chain().skip(1) {
-                        eprintln!("  caused by: {}", cause);
-                    }
-                }
-            }
+            validator.validate_line(line_number, trimmed).await;
         }
+        validator.finish();
         return Ok(());
     }
 
     let hdfs_client = Client::new(&config.hdfs_url).context("Failed to create HDFS client")?;
     let file_storage = HdfsStorage::new(hdfs_client);
 
-    let format_parser: Arc<dyn FormatParser + Send + Sync> = Arc::new(NdJsonParser {});
+    let format_parser: Arc<dyn FormatParser + Send + Sync> =
+        Arc::new(NdJsonParser::default().with_schema_compat(schema_compat));
     let decompressor: Box<dyn Decompressor + Send + Sync> = Box::new(GzipDecompressor {});
 
     let ledger_storage_config = LedgerStorageConfig {
@@ -91,20 +107,35 @@ async fn main() -> Result<()> {
     };
     let ledger_storage = LedgerStorage::new_with_config(ledger_storage_config).await;
 
-    let block_processor: Box<dyn BlockProcessorTrait + Send + Sync> = Box::new(BlockProcessor::new(ledger_storage.clone()));
-    let processor = FileProcessor::new(
+    let block_processor: Box<dyn BlockProcessorTrait + Send + Sync> = Box::new(
+        BlockProcessor::new(ledger_storage.clone())
+            .with_conversion_config(conversion_config)
+            .with_sanity_rules(sanity_rules)
+            .with_signature_verifier(signature_verifier),
+    );
+    let file_processor = FileProcessor::new_with_config(
         file_storage,
         format_parser.clone(),
         block_processor,
         decompressor,
+        file_processor_config,
     );
 
+    let processor = match file_registry_table {
+        Some(table) => {
+            let connection =
+                HBaseConnection::new(&config.hbase_address, config.namespace.as_deref()).await;
+            file_processor.with_registry(Arc::new(HBaseFileRegistry::new(connection, &table)))
+        }
+        None => file_processor,
+    };
+
     while let Some(line) = lines.next_line().await? {
         let trimmed = line.trim();
         if trimmed.is_empty() {
             continue;
         }
-        match decoder.decode(trimmed.as_bytes()).await {
+        match decoder.decode(trimmed.as_bytes(), &MessageHeaders::default()).await {
             Ok(decoded) => {
                 if let Err(e) = processor.process_decoded(decoded).await {
                     eprintln!("Error processing input: {:#?}", e);
@@ -0,0 +1,469 @@
+use {
+    anyhow::{Context, Result},
+    ingestor_kafka_hdfs::{
+        block_envelope::{
+            decode_envelope, decode_envelope_str, envelope_block_id, envelope_shape, BlockEnvelope,
+            EnvelopePayload, ENVELOPE_VERSION,
+        },
+        block_processor::{
+            convert_encoded_block, validate_block_entries, BlockConversionConfig, ConvertedBlock,
+        },
+        block_sanity::SanityRules,
+        json_utils::JsonPathError,
+        ledger_storage::{LedgerStorage, TableWrite, UploadReport},
+        schema_compat::SchemaCompat,
+    },
+    serde::Serialize,
+    std::collections::{BTreeMap, HashMap},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Human readable lines on stderr.
+    Text,
+    /// One JSON line per block on stdout, then a summary line.
+    Json,
+}
+
+/// Validation result of one block, or of an input line without a block.
+#[derive(Serialize)]
+struct BlockReport {
+    /// Line number of the input, starting at 1.
+    line: usize,
+    /// Position of the block in a multi-block batch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch_index: Option<usize>,
+    slot: Option<u64>,
+    shape: Option<String>,
+    transactions: Option<usize>,
+    entries: Option<usize>,
+    parsed: bool,
+    converted: bool,
+    error: Option<String>,
+    /// Path of the value that failed to deserialize, e.g. `transactions[5].meta.fee`.
+    error_path: Option<String>,
+    transaction_index: Option<usize>,
+    /// Rows the upload would write and the transactions filters would drop, in dry runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upload: Option<UploadReport>,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    inputs: usize,
+    blocks: usize,
+    parsed: usize,
+    converted: usize,
+    failed: usize,
+    /// Deserialization error paths with array indexes removed, most frequent first.
+    error_paths: Vec<ErrorPathCount>,
+    errors_without_path: usize,
+    schema_compat_rules: BTreeMap<&'static str, u64>,
+    sanity_violations: BTreeMap<&'static str, u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dry_run: Option<DryRunTotals>,
+}
+
+/// Totals of the upload reports of all blocks in a dry run.
+#[derive(Default, Serialize)]
+struct DryRunTotals {
+    tables: BTreeMap<String, TableWrite>,
+    /// Number of dropped transactions by table name and filter.
+    dropped_transactions: BTreeMap<String, BTreeMap<&'static str, usize>>,
+    dropped_addresses: BTreeMap<&'static str, u64>,
+}
+
+impl DryRunTotals {
+    fn add(&mut self, upload: &UploadReport) {
+        for (table_name, write) in &upload.tables {
+            let total = self.tables.entry(table_name.clone()).or_default();
+            total.rows += write.rows;
+            total.bytes += write.bytes;
+        }
+        for (table_name, filters) in &upload.dropped_transactions {
+            let total = self
+                .dropped_transactions
+                .entry(table_name.clone())
+                .or_default();
+            for (filter, signatures) in filters {
+                *total.entry(filter).or_default() += signatures.len();
+            }
+        }
+        for (filter, count) in &upload.dropped_addresses {
+            *self.dropped_addresses.entry(filter).or_default() += count;
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SummaryLine<'a> {
+    summary: &'a Summary,
+}
+
+#[derive(Serialize)]
+struct ErrorPathCount {
+    path: String,
+    count: usize,
+}
+
+/// Validates stdin lines with the same envelope decoding used for Kafka messages and file
+/// records, and the same block conversion used before upload. In dry runs each converted block
+/// is also uploaded to a storage that writes nothing, to report what the upload would write.
+pub struct Validator<'a> {
+    format: ReportFormat,
+    conversion_config: &'a BlockConversionConfig,
+    schema_compat: &'a SchemaCompat,
+    sanity_rules: Option<&'a SanityRules>,
+    dry_run_storage: Option<&'a LedgerStorage>,
+    inputs: usize,
+    blocks: usize,
+    parsed: usize,
+    converted: usize,
+    succeeded: usize,
+    dry_run_totals: DryRunTotals,
+    error_paths: HashMap<String, usize>,
+    errors_without_path: usize,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(
+        format: ReportFormat,
+        conversion_config: &'a BlockConversionConfig,
+        schema_compat: &'a SchemaCompat,
+    ) -> Self {
+        Self {
+            format,
+            conversion_config,
+            schema_compat,
+            sanity_rules: None,
+            dry_run_storage: None,
+            inputs: 0,
+            blocks: 0,
+            parsed: 0,
+            converted: 0,
+            succeeded: 0,
+            dry_run_totals: DryRunTotals::default(),
+            error_paths: HashMap::new(),
+            errors_without_path: 0,
+        }
+    }
+
+    /// Check converted blocks against sanity rules, as the block processor does.
+    pub fn with_sanity_rules(mut self, sanity_rules: &'a SanityRules) -> Self {
+        self.sanity_rules = Some(sanity_rules);
+        self
+    }
+
+    /// Upload converted blocks to a dry-run storage, see `LedgerStorage::new_dry_run`.
+    pub fn with_dry_run(mut self, storage: &'a LedgerStorage) -> Self {
+        self.dry_run_storage = Some(storage);
+        self
+    }
+
+    pub async fn validate_line(&mut self, line_number: usize, line: &str) {
+        self.inputs += 1;
+        // Blocks are parsed in place, and the line is still needed to report errors
+        match decode_envelope_str(&mut line.to_string(), self.schema_compat) {
+            Ok(Some(EnvelopePayload::Batch(blocks))) => {
+                if self.format == ReportFormat::Text {
+                    eprintln!("Parsed batch of {} blocks", blocks.len());
+                }
+                for block in blocks {
+                    self.validate_envelope(
+                        line_number,
+                        Some(block.index),
+                        &block.raw,
+                        block.envelope,
+                    )
+                    .await;
+                }
+            }
+            Ok(Some(EnvelopePayload::Block(envelope))) => {
+                self.validate_envelope(line_number, None, line, Ok(envelope))
+                    .await
+            }
+            // Not an envelope: decode it as a single block to report why
+            Ok(None) => {
+                let envelope = decode_envelope(line.to_string(), self.schema_compat);
+                self.validate_envelope(line_number, None, line, envelope)
+                    .await
+            }
+            Err(e) => {
+                self.validate_envelope(line_number, None, line, Err(e))
+                    .await
+            }
+        }
+    }
+
+    async fn validate_envelope(
+        &mut self,
+        line: usize,
+        batch_index: Option<usize>,
+        raw: &str,
+        envelope: Result<BlockEnvelope>,
+    ) {
+        self.blocks += 1;
+        let mut report = BlockReport {
+            line,
+            batch_index,
+            slot: None,
+            shape: None,
+            transactions: None,
+            entries: None,
+            parsed: envelope.is_ok(),
+            converted: false,
+            error: None,
+            error_path: None,
+            transaction_index: None,
+            upload: None,
+        };
+        let conversion_config = self.conversion_config;
+        let sanity_rules = self.sanity_rules;
+        let dry_run_storage = self.dry_run_storage;
+        let result = async {
+            let BlockEnvelope {
+                block_id,
+                block,
+                entries,
+                shape,
+            } = envelope?;
+            report.slot = Some(block_id);
+            report.shape = Some(shape.to_string());
+            report.transactions = Some(block.transactions.len());
+            report.entries = Some(entries.len());
+            let entries = validate_block_entries(
+                block_id,
+                entries,
+                block.transactions.len(),
+                &block.blockhash,
+                conversion_config.entries_validation,
+            )?;
+            let ConvertedBlock {
+                block,
+                synthesized_meta_count,
+                transactions_omitted,
+            } = convert_encoded_block(block_id, block, conversion_config)?;
+            report.converted = true;
+            if let Some(sanity_rules) = sanity_rules {
+                sanity_rules.check_block(block_id, &block)?;
+            }
+            let Some(storage) = dry_run_storage else {
+                return Ok(());
+            };
+            let upload = storage
+                .upload_block_with_entries(
+                    block_id,
+                    block,
+                    entries,
+                    synthesized_meta_count,
+                    transactions_omitted,
+                )
+                .await
+                .with_context(|| format!("Dry run upload of block={block_id} failed"))?;
+            report.upload = Some(upload);
+            Ok(())
+        }
+        .await;
+
+        if report.parsed {
+            self.parsed += 1;
+        }
+        if report.converted {
+            self.converted += 1;
+        }
+        match &result {
+            Ok(()) => {
+                self.succeeded += 1;
+                if let Some(upload) = &report.upload {
+                    self.dry_run_totals.add(upload);
+                }
+            }
+            Err(e) => {
+                if report.slot.is_none() {
+                    report.slot = envelope_block_id(raw);
+                    report.shape = envelope_shape(raw).map(|shape| shape.to_string());
+                }
+                report.error = Some(format!("{e:#}"));
+                match JsonPathError::find(e) {
+                    Some(path_error) => {
+                        *self
+                            .error_paths
+                            .entry(path_pattern(&path_error.path))
+                            .or_default() += 1;
+                        report.error_path = Some(path_error.path.clone());
+                        report.transaction_index = path_error.transaction_index;
+                    }
+                    None => self.errors_without_path += 1,
+                }
+            }
+        }
+
+        match self.format {
+            ReportFormat::Text => print_text_report(&report, &result),
+            ReportFormat::Json => match serde_json::to_string(&report) {
+                Ok(json) => println!("{json}"),
+                Err(e) => eprintln!("Failed to serialize validation report: {e}"),
+            },
+        }
+    }
+
+    pub fn finish(self) {
+        let mut error_paths: Vec<ErrorPathCount> = self
+            .error_paths
+            .into_iter()
+            .map(|(path, count)| ErrorPathCount { path, count })
+            .collect();
+        error_paths.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.path.cmp(&b.path)));
+
+        let summary = Summary {
+            inputs: self.inputs,
+            blocks: self.blocks,
+            parsed: self.parsed,
+            converted: self.converted,
+            failed: self.blocks - self.succeeded,
+            error_paths,
+            errors_without_path: self.errors_without_path,
+            schema_compat_rules: self
+                .schema_compat
+                .rule_counts()
+                .into_iter()
+                .filter(|(_, count)| *count > 0)
+                .map(|(rule, count)| (rule.name(), count))
+                .collect(),
+            sanity_violations: self
+                .sanity_rules
+                .map(|sanity_rules| sanity_rules.violation_counts())
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(_, count)| *count > 0)
+                .map(|(rule, count)| (rule.name(), count))
+                .collect(),
+            dry_run: self.dry_run_storage.map(|_| self.dry_run_totals),
+        };
+
+        match self.format {
+            ReportFormat::Text => print_text_summary(&summary),
+            ReportFormat::Json => match serde_json::to_string(&SummaryLine { summary: &summary }) {
+                Ok(json) => println!("{json}"),
+                Err(e) => eprintln!("Failed to serialize validation summary: {e}"),
+            },
+        }
+    }
+}
+
+fn print_text_report(report: &BlockReport, result: &Result<()>) {
+    let prefix = report
+        .batch_index
+        .map(|index| format!("[{index}] "))
+        .unwrap_or_default();
+    match result {
+        Ok(()) => eprintln!(
+            "{prefix}Parsed block (envelope v{ENVELOPE_VERSION}, shape={}): \
+             blockID={}, transactions={}, entries={}",
+            report.shape.as_deref().unwrap_or_default(),
+            report.slot.unwrap_or_default(),
+            report.transactions.unwrap_or_default(),
+            report.entries.unwrap_or_default(),
+        ),
+        Err(e) => {
+            eprintln!("{prefix}Failed to decode input: {e}");
+            for cause in e.chain().skip(1) {
+                eprintln!("{prefix}  caused by: {cause}");
+            }
+        }
+    }
+    if let Some(upload) = &report.upload {
+        print_text_upload(
+            &prefix,
+            &upload.tables,
+            &upload
+                .dropped_transactions
+                .iter()
+                .map(|(table_name, filters)| {
+                    let counts = filters
+                        .iter()
+                        .map(|(filter, signatures)| (*filter, signatures.len()))
+                        .collect();
+                    (table_name.clone(), counts)
+                })
+                .collect(),
+            &upload.dropped_addresses,
+        );
+    }
+}
+
+/// Print the rows and bytes a dry run would write per table, and what the filters dropped.
+fn print_text_upload(
+    prefix: &str,
+    tables: &BTreeMap<String, TableWrite>,
+    dropped_transactions: &BTreeMap<String, BTreeMap<&'static str, usize>>,
+    dropped_addresses: &BTreeMap<&'static str, u64>,
+) {
+    for (table_name, write) in tables {
+        eprintln!(
+            "{prefix}  would write {} rows, {} bytes to {table_name}",
+            write.rows, write.bytes
+        );
+    }
+    for (table_name, filters) in dropped_transactions {
+        for (filter, count) in filters {
+            eprintln!("{prefix}  {filter} drops {count} transactions from {table_name}");
+        }
+    }
+    for (filter, count) in dropped_addresses {
+        eprintln!("{prefix}  {filter} drops {count} addresses from tx-by-addr");
+    }
+}
+
+fn print_text_summary(summary: &Summary) {
+    eprintln!(
+        "Validated {} inputs: {} blocks, {} converted, {} failed",
+        summary.inputs, summary.blocks, summary.converted, summary.failed
+    );
+    if !summary.error_paths.is_empty() {
+        eprintln!("Error paths by frequency:");
+        for ErrorPathCount { path, count } in &summary.error_paths {
+            eprintln!("  {count:>8}  {path}");
+        }
+    }
+    if summary.errors_without_path > 0 {
+        eprintln!("Errors without a path: {}", summary.errors_without_path);
+    }
+    for (rule, count) in &summary.schema_compat_rules {
+        eprintln!("Schema compatibility rule {rule} applied {count} times");
+    }
+    for (rule, count) in &summary.sanity_violations {
+        eprintln!("Sanity rule {rule} failed for {count} blocks");
+    }
+    if let Some(totals) = &summary.dry_run {
+        eprintln!("Dry run totals:");
+        print_text_upload(
+            "",
+            &totals.tables,
+            &totals.dropped_transactions,
+            &totals.dropped_addresses,
+        );
+    }
+}
+
+/// Replace array indexes in a path with `[]`, so that e.g. the same field failing in different
+/// transactions is counted as one path.
+fn path_pattern(path: &str) -> String {
+    let mut pattern = String::with_capacity(path.len());
+    let mut in_index = false;
+    for ch in path.chars() {
+        match ch {
+            '[' => {
+                in_index = true;
+                pattern.push(ch);
+            }
+            ']' => {
+                in_index = false;
+                pattern.push(ch);
+            }
+            _ if in_index => {}
+            _ => pattern.push(ch),
+        }
+    }
+    pattern
+}
@@ -0,0 +1,590 @@
+use {
+    crate::{
+        entries_parser::parse_entries_from_value,
+        json_utils::{
+            from_slice_fast_with_path, from_value_with_path, FastParseError, IgnoredKeysError,
+        },
+        schema_compat::{SchemaCompat, SchemaCompatMode},
+        transaction_encoding::check_transaction_encoding,
+    },
+    anyhow::{anyhow, Context, Result},
+    serde::{de::IgnoredAny, Deserialize, Deserializer},
+    serde_json::{value::RawValue, Value},
+    solana_block_decoder::block::encoded_block::{
+        EncodedConfirmedBlock, EncodedTransactionWithStatusMeta,
+    },
+    solana_sdk::clock::{Slot, UnixTimestamp},
+    solana_transaction_status::{EntrySummary, Rewards, UiTransactionEncoding},
+    std::{fmt, ops::Range},
+};
+
+/// Version of the envelope format, bumped whenever a shape is added or its handling changes.
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// The block envelope shapes understood by the ingestor. `blockID` may be a number or a
+/// numeric string in every shape, and `entries` is always optional. An optional `encoding` next to
+/// `entries` states the getBlock transaction encoding; without it the encoding is auto-detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeShape {
+    /// `{ "blockID": N, ...block fields, "entries": [...] }`
+    TopLevel,
+    /// `{ "block": { "blockID": N, ...block fields }, "entries": [...] }`
+    Nested,
+    /// `{ "jsonrpc": "2.0", "result": { "blockID": N, ... }, "id": n }`
+    RpcTopLevel,
+    /// `{ "jsonrpc": "2.0", "result": { "block": { "blockID": N, ... }, ... }, "id": n }`
+    RpcNested,
+}
+
+pub const SUPPORTED_SHAPES: &[EnvelopeShape] = &[
+    EnvelopeShape::TopLevel,
+    EnvelopeShape::Nested,
+    EnvelopeShape::RpcTopLevel,
+    EnvelopeShape::RpcNested,
+];
+
+impl EnvelopeShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnvelopeShape::TopLevel => "top-level",
+            EnvelopeShape::Nested => "nested",
+            EnvelopeShape::RpcTopLevel => "rpc-top-level",
+            EnvelopeShape::RpcNested => "rpc-nested",
+        }
+    }
+}
+
+impl fmt::Display for EnvelopeShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A block decoded from one of the supported envelope shapes.
+pub struct BlockEnvelope {
+    pub block_id: u64,
+    pub block: EncodedConfirmedBlock,
+    pub entries: Vec<EntrySummary>,
+    pub shape: EnvelopeShape,
+}
+
+/// Detect which supported shape an envelope has, if any.
+/// A payload without a shape is not a block envelope, e.g. a file path message.
+pub fn envelope_shape(json: &str) -> Option<EnvelopeShape> {
+    shaped_keys(json).map(|(shape, ..)| shape)
+}
+
+/// The `blockID` of a block envelope in any supported shape, if it has a valid one.
+pub fn envelope_block_id(json: &str) -> Option<u64> {
+    let (shape, _, keys) = shaped_keys(json)?;
+    let block_id = match shape {
+        EnvelopeShape::TopLevel | EnvelopeShape::RpcTopLevel => keys.block_id,
+        EnvelopeShape::Nested | EnvelopeShape::RpcNested => parse_keys(keys.block?.get())?.block_id,
+    };
+    block_id_from(raw_to_value(block_id).ok()?).ok()
+}
+
+/// Decode a single block envelope from JSON text. `blockID`, `entries` and `encoding` are
+/// taken from the envelope before the remaining block fields are parsed in place, whatever the
+/// shape.
+pub fn decode_envelope(json: String, compat: &SchemaCompat) -> Result<BlockEnvelope> {
+    let parts = find_envelope(&json, &json)?;
+    parts.decode(&mut json.into_bytes(), compat)
+}
+
+/// One block of a multi-block batch. Blocks that fail to decode are kept, so they can be
+/// reported on their own.
+pub struct BatchBlock {
+    /// Position of the block in the batch.
+    pub index: usize,
+    /// Raw JSON of the block envelope.
+    pub raw: String,
+    pub envelope: Result<BlockEnvelope>,
+}
+
+/// A block envelope or a multi-block batch, decoded straight from JSON text.
+pub enum EnvelopePayload {
+    Block(BlockEnvelope),
+    /// `{ "blocks": [<envelope>, ...] }` or a top-level array of envelopes.
+    Batch(Vec<BatchBlock>),
+}
+
+/// Decode a block envelope or a multi-block batch from JSON text. Only the envelope keys are
+/// looked at before the block is deserialized, so no `Value` is built for the block itself.
+/// Blocks are parsed in place, which consumes the text: it is left empty unless `Ok(None)` is
+/// returned for text that is not JSON or has none of the supported shapes, or the envelope keys
+/// are invalid.
+pub fn decode_envelope_str(
+    json: &mut String,
+    compat: &SchemaCompat,
+) -> Result<Option<EnvelopePayload>> {
+    let Some(layout) = payload_layout(json)? else {
+        return Ok(None);
+    };
+    let mut text = std::mem::take(json).into_bytes();
+    Ok(Some(match layout {
+        PayloadLayout::Block(parts) => EnvelopePayload::Block(parts.decode(&mut text, compat)?),
+        PayloadLayout::Batch(elements) => EnvelopePayload::Batch(
+            elements
+                .into_iter()
+                .enumerate()
+                .map(|(index, (raw, parts))| BatchBlock {
+                    index,
+                    raw,
+                    envelope: parts.and_then(|parts| parts.decode(&mut text, compat)),
+                })
+                .collect(),
+        ),
+    }))
+}
+
+/// The envelopes found in a payload, before their blocks are parsed.
+enum PayloadLayout {
+    Block(EnvelopeParts),
+    /// The raw JSON of each envelope of a batch, with its parts.
+    Batch(Vec<(String, Result<EnvelopeParts>)>),
+}
+
+fn payload_layout(json: &str) -> Result<Option<PayloadLayout>> {
+    if json.trim_start().starts_with('[') {
+        return Ok(parse_batch_elements(json).map(|elements| batch_layout(json, elements)));
+    }
+    let Some(keys) = parse_keys(json) else {
+        return Ok(None);
+    };
+    if let Some(elements) = keys
+        .blocks
+        .and_then(|blocks| parse_batch_elements(blocks.get()))
+    {
+        return Ok(Some(batch_layout(json, elements)));
+    }
+    shape_keys(json, keys)
+        .map(|(shape, inner, keys)| envelope_parts(json, inner, shape, keys))
+        .transpose()
+        .map(|parts| parts.map(PayloadLayout::Block))
+}
+
+/// Find the envelope of every element of a batch. Failures of individual elements are kept
+/// with them, so they can be reported in their `BatchBlock`.
+fn batch_layout(text: &str, elements: Vec<&RawValue>) -> PayloadLayout {
+    PayloadLayout::Batch(
+        elements
+            .into_iter()
+            .map(|element| {
+                let raw = element.get();
+                (raw.to_string(), find_envelope(text, raw))
+            })
+            .collect(),
+    )
+}
+
+/// Parse the top-level keys of a JSON object, or `None` if the text is not one.
+fn parse_keys(json: &str) -> Option<EnvelopeKeys> {
+    if !json.trim_start().starts_with('{') {
+        return None;
+    }
+    serde_json::from_str(json).ok()
+}
+
+fn shaped_keys(json: &str) -> Option<(EnvelopeShape, &str, EnvelopeKeys)> {
+    shape_keys(json, parse_keys(json)?)
+}
+
+/// The shape of an envelope, with the text and keys of the object that holds its block fields
+/// or `block`: the envelope itself, or its JSON-RPC `result`.
+fn shape_keys<'a>(
+    json: &'a str,
+    keys: EnvelopeKeys<'a>,
+) -> Option<(EnvelopeShape, &'a str, EnvelopeKeys<'a>)> {
+    // Support JSON-RPC wrapper: { "jsonrpc": "2.0", "result": { ... }, "id": n }
+    let (json, keys, rpc_wrapped) = match keys.result {
+        Some(result) => (result.get(), parse_keys(result.get())?, true),
+        None => (json, keys, false),
+    };
+    let shape = match (keys.block_id.is_some(), keys.block.is_some(), rpc_wrapped) {
+        (true, _, false) => EnvelopeShape::TopLevel,
+        (true, _, true) => EnvelopeShape::RpcTopLevel,
+        (false, true, false) => EnvelopeShape::Nested,
+        (false, true, true) => EnvelopeShape::RpcNested,
+        (false, false, _) => return None,
+    };
+    Some((shape, json, keys))
+}
+
+/// Top-level keys of an envelope. Values are borrowed from the JSON text unparsed, so looking
+/// at the keys costs a scan of the text without allocating the block.
+#[derive(Deserialize)]
+struct EnvelopeKeys<'a> {
+    #[serde(borrow, default, deserialize_with = "present")]
+    result: Option<&'a RawValue>,
+    #[serde(rename = "blockID", borrow, default, deserialize_with = "present")]
+    block_id: Option<&'a RawValue>,
+    #[serde(borrow, default, deserialize_with = "present")]
+    block: Option<&'a RawValue>,
+    #[serde(borrow, default, deserialize_with = "present")]
+    entries: Option<&'a RawValue>,
+    #[serde(borrow, default, deserialize_with = "present")]
+    encoding: Option<&'a RawValue>,
+    #[serde(borrow, default, deserialize_with = "present")]
+    blocks: Option<&'a RawValue>,
+}
+
+/// Keep keys whose value is `null`, which `Option` would otherwise treat as absent.
+fn present<'de, D>(deserializer: D) -> std::result::Result<Option<&'de RawValue>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    <&RawValue>::deserialize(deserializer).map(Some)
+}
+
+/// The envelope keys of a block envelope, parsed, and where its block fields are in the text.
+struct EnvelopeParts {
+    shape: EnvelopeShape,
+    block_id: u64,
+    entries: Vec<EntrySummary>,
+    encoding: Option<UiTransactionEncoding>,
+    block: Range<usize>,
+}
+
+impl EnvelopeParts {
+    /// Parse the block fields in place in `text`, the text the parts were found in.
+    fn decode(self, text: &mut [u8], compat: &SchemaCompat) -> Result<BlockEnvelope> {
+        let EnvelopeParts {
+            shape,
+            block_id,
+            entries,
+            encoding,
+            block,
+        } = self;
+        let block = parse_block(&mut text[block], block_id, compat);
+        let block = match shape {
+            EnvelopeShape::TopLevel | EnvelopeShape::RpcTopLevel => block?,
+            EnvelopeShape::Nested | EnvelopeShape::RpcNested => {
+                block.context("Failed to parse EncodedConfirmedBlock from block field")?
+            }
+        };
+        check_encoding(&block, encoding, block_id)?;
+        Ok(BlockEnvelope {
+            block_id,
+            block,
+            entries,
+            shape,
+        })
+    }
+}
+
+/// Find the envelope in `json`, a part of `text`, failing if it has no supported shape.
+fn find_envelope(text: &str, json: &str) -> Result<EnvelopeParts> {
+    if let Some((shape, inner, keys)) = shaped_keys(json) {
+        return envelope_parts(text, inner, shape, keys);
+    }
+    serde_json::from_str::<IgnoredAny>(json).context("Failed to parse JSON")?;
+    Err(anyhow!(
+        "Payload is not a block envelope (supported shapes: {})",
+        SUPPORTED_SHAPES
+            .iter()
+            .map(EnvelopeShape::name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+/// Parse the envelope keys of `json`, a part of `text` with the given shape.
+fn envelope_parts(
+    text: &str,
+    json: &str,
+    shape: EnvelopeShape,
+    keys: EnvelopeKeys,
+) -> Result<EnvelopeParts> {
+    let (block_id, block_json) = match shape {
+        EnvelopeShape::TopLevel | EnvelopeShape::RpcTopLevel => {
+            (block_id_from(raw_to_value(keys.block_id)?)?, json)
+        }
+        EnvelopeShape::Nested | EnvelopeShape::RpcNested => {
+            let block_json = keys.block.map(RawValue::get).unwrap_or_default();
+            let block_keys =
+                parse_keys(block_json).ok_or_else(|| anyhow!("block field must be an object"))?;
+            let block_id = block_id_from(raw_to_value(block_keys.block_id)?)
+                .context("Missing or invalid block.blockID in payload")?;
+            (block_id, block_json)
+        }
+    };
+    Ok(EnvelopeParts {
+        shape,
+        block_id,
+        entries: entries_from(raw_to_value(keys.entries)?, block_id)?,
+        encoding: encoding_from(raw_to_value(keys.encoding)?)?,
+        block: range_in(text, block_json),
+    })
+}
+
+/// Position of `part`, a slice of `text`, in `text`.
+fn range_in(text: &str, part: &str) -> Range<usize> {
+    let start = part.as_ptr() as usize - text.as_ptr() as usize;
+    start..start + part.len()
+}
+
+fn parse_batch_elements(json: &str) -> Option<Vec<&RawValue>> {
+    serde_json::from_str(json).ok()
+}
+
+/// Parse a small borrowed value, such as `blockID` or `entries`.
+fn raw_to_value(raw: Option<&RawValue>) -> Result<Option<Value>> {
+    raw.map(|raw| serde_json::from_str(raw.get()).map_err(Into::into))
+        .transpose()
+}
+
+/// Parse `blockID` from a number or a numeric string.
+fn block_id_from(value: Option<Value>) -> Result<u64> {
+    match value {
+        Some(Value::Number(n)) => n
+            .as_u64()
+            .ok_or_else(|| anyhow!("blockID must be an unsigned integer: {n}")),
+        Some(Value::String(s)) => s
+            .parse::<u64>()
+            .with_context(|| format!("Failed to parse blockID string as u64: {s}")),
+        Some(other) => Err(anyhow!("blockID must be a number or a string: {other}")),
+        None => Err(anyhow!("Missing blockID")),
+    }
+}
+
+fn entries_from(value: Option<Value>, block_id: u64) -> Result<Vec<EntrySummary>> {
+    match value {
+        Some(entries_value) => parse_entries_from_value(&entries_value)
+            .with_context(|| format!("Failed to parse entries field - slot={block_id}")),
+        None => Ok(vec![]),
+    }
+}
+
+fn encoding_from(value: Option<Value>) -> Result<Option<UiTransactionEncoding>> {
+    value
+        .map(|encoding| {
+            serde_json::from_value(encoding.clone())
+                .with_context(|| format!("Unsupported transaction encoding: {encoding}"))
+        })
+        .transpose()
+}
+
+fn check_encoding(
+    block: &EncodedConfirmedBlock,
+    encoding: Option<UiTransactionEncoding>,
+    block_id: u64,
+) -> Result<()> {
+    match encoding {
+        Some(encoding) => check_transaction_encoding(block, encoding)
+            .with_context(|| format!("Block does not match its stated encoding - slot={block_id}")),
+        None => Ok(()),
+    }
+}
+
+/// The block fields of an envelope. Mirrors `EncodedConfirmedBlock`; the envelope keys that
+/// may sit next to them are skipped. Blocks that omit `rewards` or `transactions`, as getBlock
+/// does with rewards=false or transactionDetails=none, are accepted by
+/// `CompatRule::DefaultMissingFields`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockFields {
+    previous_blockhash: String,
+    blockhash: String,
+    parent_slot: Slot,
+    transactions: Vec<EncodedTransactionWithStatusMeta>,
+    rewards: Rewards,
+    num_partitions: Option<u64>,
+    block_time: Option<UnixTimestamp>,
+    block_height: Option<u64>,
+    #[serde(rename = "blockID", default, deserialize_with = "skip")]
+    _block_id: (),
+    #[serde(default, deserialize_with = "skip")]
+    _entries: (),
+    #[serde(default, deserialize_with = "skip")]
+    _encoding: (),
+}
+
+/// Skip a value without reporting its key as ignored, unlike `IgnoredAny`.
+fn skip<'de, D>(deserializer: D) -> std::result::Result<(), D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(IgnoredAny).map(|_| ())
+}
+
+impl From<BlockFields> for EncodedConfirmedBlock {
+    fn from(fields: BlockFields) -> Self {
+        Self {
+            previous_blockhash: fields.previous_blockhash,
+            blockhash: fields.blockhash,
+            parent_slot: fields.parent_slot,
+            transactions: fields.transactions,
+            rewards: fields.rewards,
+            num_partitions: fields.num_partitions,
+            block_time: fields.block_time,
+            block_height: fields.block_height,
+        }
+    }
+}
+
+fn parse_block(
+    block_json: &mut [u8],
+    block_id: u64,
+    compat: &SchemaCompat,
+) -> Result<EncodedConfirmedBlock> {
+    let (block_value, error) = match compat.mode() {
+        SchemaCompatMode::Strict => (
+            serde_json::from_slice(block_json)
+                .with_context(|| format!("Failed to parse block JSON - slot={block_id}"))?,
+            None,
+        ),
+        mode => match from_slice_fast_with_path::<BlockFields>(block_json, "EncodedConfirmedBlock")
+        {
+            Ok(fields) => return Ok(fields.into()),
+            // Ignored keys may be drift the rules fix, such as legacy keys of optional fields,
+            // so the normalized block is parsed even if no rule fails it as it is
+            Err(FastParseError {
+                error,
+                value: Some(value),
+            }) if mode == SchemaCompatMode::Lenient => {
+                (value, (!error.is::<IgnoredKeysError>()).then_some(error))
+            }
+            Err(FastParseError {
+                error,
+                value: Some(value),
+            }) if error.is::<IgnoredKeysError>() => {
+                return from_value_with_path::<BlockFields>(value, "EncodedConfirmedBlock")
+                    .map(Into::into)
+                    .with_context(|| {
+                        format!("Failed to parse EncodedConfirmedBlock - slot={block_id}")
+                    })
+            }
+            Err(FastParseError { error, value: None }) if mode == SchemaCompatMode::Lenient => {
+                return Err(error)
+                    .with_context(|| format!("Failed to parse block JSON - slot={block_id}"))
+            }
+            Err(FastParseError { error, .. }) => {
+                return Err(error).with_context(|| {
+                    format!("Failed to parse EncodedConfirmedBlock - slot={block_id}")
+                })
+            }
+        },
+    };
+    parse_normalized_block(block_value, block_id, compat, error)
+        .with_context(|| format!("Failed to parse EncodedConfirmedBlock - slot={block_id}"))
+}
+
+/// Apply the schema compatibility rules and parse the block. `error` is the error of parsing
+/// the block as it is, which is returned as is if no rule applies.
+fn parse_normalized_block(
+    mut block_value: Value,
+    block_id: u64,
+    compat: &SchemaCompat,
+    error: Option<anyhow::Error>,
+) -> Result<EncodedConfirmedBlock> {
+    let applied = compat.normalize_block(&mut block_value, block_id)?;
+    match error {
+        Some(error) if applied == 0 => Err(error),
+        _ => from_value_with_path::<BlockFields>(block_value, "EncodedConfirmedBlock")
+            .map(Into::into),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, crate::schema_compat::CompatRule,
+        solana_transaction_status::option_serializer::OptionSerializer,
+    };
+
+    /// A block with one vote transaction, holding `fields` as top-level envelope keys.
+    fn envelope_json(fields: &str) -> String {
+        format!(
+            r#"{{
+                "blockID": 300000000,
+                {fields}
+                "previousBlockhash": "JCu1pPv9ie31W2iiCfbgw24dE6Qhrd8hSqyfuTX72KA9",
+                "blockhash": "13VwGEw4m1HcZeSVqxJvVYZu91DV2Jkk7sY5uv6WCHn9",
+                "parentSlot": 299999999,
+                "transactions": [{{
+                    "meta": {{
+                        "err": null,
+                        "status": {{"Ok": null}},
+                        "fee": 5000,
+                        "preBalances": [1500000000, 1],
+                        "postBalances": [1499995000, 1],
+                        "compute_units_consumed": 2100
+                    }},
+                    "transaction": ["AQID", "base64"]
+                }}],
+                "rewards": []
+            }}"#
+        )
+    }
+
+    fn decode(json: &str, compat: &SchemaCompat) -> BlockEnvelope {
+        match decode_envelope_str(&mut json.to_string(), compat) {
+            Ok(Some(EnvelopePayload::Block(envelope))) => envelope,
+            Ok(_) => panic!("not a single block envelope"),
+            Err(e) => panic!("{e:#}"),
+        }
+    }
+
+    fn applied(compat: &SchemaCompat) -> Vec<(CompatRule, u64)> {
+        compat
+            .rule_counts()
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    #[test]
+    fn test_legacy_keys_of_optional_fields_are_renamed() {
+        let compat = SchemaCompat::new(SchemaCompatMode::Lenient);
+        let envelope = decode(&envelope_json(r#""block_height": 280000000,"#), &compat);
+        assert_eq!(envelope.block.block_height, Some(280000000));
+        let meta = envelope.block.transactions[0].meta.as_ref().unwrap();
+        assert_eq!(meta.compute_units_consumed, OptionSerializer::Some(2100));
+        assert_eq!(applied(&compat), vec![(CompatRule::RenameLegacyKeys, 2)]);
+    }
+
+    #[test]
+    fn test_unknown_keys_of_the_block_are_dropped() {
+        let compat = SchemaCompat::new(SchemaCompatMode::Lenient);
+        let envelope = decode(
+            &envelope_json(r#""blockHeight": 1, "leaderSchedule": null,"#),
+            &compat,
+        );
+        assert_eq!(envelope.block.block_height, Some(1));
+        assert_eq!(
+            applied(&compat),
+            vec![
+                (CompatRule::RenameLegacyKeys, 1),
+                (CompatRule::DropUnknownFields, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_envelope_keys_are_not_drift() {
+        let json = envelope_json(r#""blockHeight": 1, "entries": [], "encoding": "base64","#)
+            .replace("compute_units_consumed", "computeUnitsConsumed");
+        let compat = SchemaCompat::new(SchemaCompatMode::Strict);
+        let envelope = decode(&json, &compat);
+        assert_eq!(envelope.block.block_height, Some(1));
+        assert_eq!(envelope.block.transactions.len(), 1);
+        let compat = SchemaCompat::new(SchemaCompatMode::Lenient);
+        decode(&json, &compat);
+        assert!(applied(&compat).is_empty());
+    }
+
+    #[test]
+    fn test_legacy_keys_without_compat() {
+        let compat = SchemaCompat::new(SchemaCompatMode::Off);
+        let envelope = decode(&envelope_json(r#""block_height": 280000000,"#), &compat);
+        assert_eq!(envelope.block.block_height, None);
+        assert!(applied(&compat).is_empty());
+
+        let compat = SchemaCompat::new(SchemaCompatMode::Strict);
+        let err = decode_envelope_str(&mut envelope_json(r#""block_height": 1,"#), &compat)
+            .err()
+            .unwrap();
+        assert!(format!("{err:#}").contains("`block_height`"), "{err:#}");
+    }
+}
@@ -1,8 +1,21 @@
 use {
-    crate::ledger_storage::LedgerStorage,
-    anyhow::{Context, Result},
+    crate::{
+        block_sanity::SanityRules,
+        entries_parser::check_entries_consistency,
+        ledger_storage::LedgerStorage,
+        signature_verification::{SignatureVerificationPolicy, SignatureVerifier},
+        transaction_encoding::normalize_transaction_encoding,
+    },
+    anyhow::{anyhow, Context, Result},
     async_trait::async_trait,
-    solana_block_decoder::{block::encoded_block::EncodedConfirmedBlock, convert_block},
+    log::warn,
+    solana_block_decoder::block::{
+        confirmed_block::{
+            ConfirmedBlock as DecodedBlock, TransactionWithStatusMeta as DecodedTransaction,
+        },
+        encoded_block::EncodedConfirmedBlock,
+        ui_block::UiConfirmedBlock,
+    },
     // solana_block_decoder::{
     //     transaction_status::{
     //         BlockEncodingOptions,
@@ -12,10 +25,259 @@ use {
     //     },
     //     convert_block,
     // },
+    solana_sdk::transaction::{Transaction, TransactionVersion},
     solana_transaction_status::{BlockEncodingOptions, TransactionDetails, UiTransactionEncoding},
-    solana_transaction_status::{EntrySummary, VersionedConfirmedBlockWithEntries},
+    solana_transaction_status::{
+        ConfirmedBlock, EntrySummary, TransactionStatusMeta, TransactionWithStatusMeta,
+        VersionedConfirmedBlockWithEntries, VersionedTransactionWithStatusMeta,
+    },
+    std::str::FromStr,
 };
 
+/// What to do with transactions that have no status metadata, as found in some very old blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingMetadataPolicy {
+    /// Fail the whole block.
+    #[default]
+    Reject,
+    /// Store the transaction with empty metadata: successful, no fee and no balances.
+    Synthesize,
+    /// Store the transaction as `TransactionWithStatusMeta::MissingMetadata`.
+    Store,
+}
+
+impl FromStr for MissingMetadataPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(MissingMetadataPolicy::Reject),
+            "synthesize" => Ok(MissingMetadataPolicy::Synthesize),
+            "store" => Ok(MissingMetadataPolicy::Store),
+            other => Err(anyhow!("Unsupported missing metadata policy: {other}")),
+        }
+    }
+}
+
+/// What to do with entries that are inconsistent with their block, see
+/// `check_entries_consistency`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EntriesValidationPolicy {
+    /// Do not check entries.
+    #[default]
+    Off,
+    /// Fail the whole block.
+    Reject,
+    /// Log a warning and store the entries anyway.
+    Warn,
+    /// Log a warning and store the block without entries.
+    StoreWithoutEntries,
+}
+
+impl FromStr for EntriesValidationPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(EntriesValidationPolicy::Off),
+            "reject" => Ok(EntriesValidationPolicy::Reject),
+            "warn" => Ok(EntriesValidationPolicy::Warn),
+            "store-without-entries" => Ok(EntriesValidationPolicy::StoreWithoutEntries),
+            other => Err(anyhow!("Unsupported entries validation policy: {other}")),
+        }
+    }
+}
+
+/// Check entries against their block and apply the policy to inconsistent ones, returning the
+/// entries to store.
+pub fn validate_block_entries(
+    block_id: u64,
+    entries: Vec<EntrySummary>,
+    transaction_count: usize,
+    blockhash: &str,
+    policy: EntriesValidationPolicy,
+) -> Result<Vec<EntrySummary>> {
+    if policy == EntriesValidationPolicy::Off {
+        return Ok(entries);
+    }
+    let Err(e) = check_entries_consistency(&entries, transaction_count, blockhash) else {
+        return Ok(entries);
+    };
+    match policy {
+        EntriesValidationPolicy::Off => Ok(entries),
+        EntriesValidationPolicy::Reject => {
+            Err(e.context(format!("Inconsistent entries in block={block_id}")))
+        }
+        EntriesValidationPolicy::Warn => {
+            warn!("Inconsistent entries in block={block_id}: {e:#}");
+            Ok(entries)
+        }
+        EntriesValidationPolicy::StoreWithoutEntries => {
+            warn!("Inconsistent entries in block={block_id}, storing it without entries: {e:#}");
+            Ok(vec![])
+        }
+    }
+}
+
+/// Options applied when converting an `EncodedConfirmedBlock` into its stored form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockConversionConfig {
+    /// `Full` stores every transaction, `None` stores the block without transactions.
+    pub transaction_details: TransactionDetails,
+    /// Whether block rewards are stored.
+    pub show_rewards: bool,
+    /// Highest transaction version accepted; `None` accepts legacy transactions only.
+    pub max_supported_transaction_version: Option<u8>,
+    /// Handling of transactions without status metadata.
+    pub missing_metadata: MissingMetadataPolicy,
+    /// Handling of entries inconsistent with their block.
+    pub entries_validation: EntriesValidationPolicy,
+}
+
+impl Default for BlockConversionConfig {
+    fn default() -> Self {
+        Self {
+            transaction_details: TransactionDetails::Full,
+            show_rewards: true,
+            max_supported_transaction_version: Some(0),
+            missing_metadata: MissingMetadataPolicy::default(),
+            entries_validation: EntriesValidationPolicy::default(),
+        }
+    }
+}
+
+impl BlockConversionConfig {
+    fn encoding_options(&self) -> BlockEncodingOptions {
+        BlockEncodingOptions {
+            transaction_details: self.transaction_details,
+            show_rewards: self.show_rewards,
+            max_supported_transaction_version: self.max_supported_transaction_version,
+        }
+    }
+}
+
+/// A block converted into its stored form.
+pub struct ConvertedBlock {
+    pub block: ConfirmedBlock,
+    /// Number of transactions whose missing metadata was synthesized.
+    pub synthesized_meta_count: u64,
+    /// The transactions were dropped, as asked by `TransactionDetails::None`.
+    pub transactions_omitted: bool,
+}
+
+/// Convert a block into its stored form, applying the conversion options. Shared by the upload
+/// path and by validation, so both accept the same blocks.
+pub fn convert_encoded_block(
+    block_id: u64,
+    mut block: EncodedConfirmedBlock,
+    config: &BlockConversionConfig,
+) -> Result<ConvertedBlock> {
+    if !config.show_rewards {
+        block.rewards.clear();
+    }
+
+    let encoding = match config.transaction_details {
+        TransactionDetails::Full => {
+            check_transaction_versions(&block, config.max_supported_transaction_version)
+                .with_context(|| format!("Unsupported transaction in block={block_id}"))?;
+
+            // Blocks may come from getBlock with any transaction encoding
+            let (normalized, encoding) = normalize_transaction_encoding(block)
+                .with_context(|| format!("Unsupported transaction encoding in block={block_id}"))?;
+            block = normalized;
+            encoding
+        }
+        TransactionDetails::None => {
+            block.transactions.clear();
+            UiTransactionEncoding::Json
+        }
+        other => {
+            return Err(anyhow!(
+                "Transaction details {other:?} cannot be stored, use full or none"
+            ))
+        }
+    };
+
+    let ui_block: UiConfirmedBlock = block.into();
+    let DecodedBlock {
+        previous_blockhash,
+        blockhash,
+        parent_slot,
+        transactions,
+        rewards,
+        num_partitions,
+        block_time,
+        block_height,
+    } = DecodedBlock::decode_with_options(ui_block, encoding, config.encoding_options())
+        .map_err(|e| anyhow!("Failed to convert block={block_id}: {}", e))?;
+
+    let mut synthesized_meta_count = 0;
+    let transactions = transactions
+        .into_iter()
+        .enumerate()
+        .map(|(index, tx)| match tx {
+            DecodedTransaction::Complete(tx) => Ok(TransactionWithStatusMeta::Complete(tx.into())),
+            DecodedTransaction::MissingMetadata(tx) => {
+                let transaction = Transaction {
+                    signatures: tx.signatures,
+                    message: tx.message.into(),
+                };
+                match config.missing_metadata {
+                    MissingMetadataPolicy::Reject => Err(anyhow!(
+                        "Transaction {} has no status metadata (block={block_id}, transaction index: {index})",
+                        transaction.signatures.first().copied().unwrap_or_default()
+                    )),
+                    MissingMetadataPolicy::Synthesize => {
+                        synthesized_meta_count += 1;
+                        Ok(TransactionWithStatusMeta::Complete(
+                            VersionedTransactionWithStatusMeta {
+                                transaction: transaction.into(),
+                                meta: TransactionStatusMeta::default(),
+                            },
+                        ))
+                    }
+                    MissingMetadataPolicy::Store => {
+                        Ok(TransactionWithStatusMeta::MissingMetadata(transaction))
+                    }
+                }
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ConvertedBlock {
+        block: ConfirmedBlock {
+            previous_blockhash,
+            blockhash,
+            parent_slot,
+            transactions,
+            rewards,
+            num_partitions,
+            block_time,
+            block_height,
+        },
+        synthesized_meta_count,
+        transactions_omitted: config.transaction_details == TransactionDetails::None,
+    })
+}
+
+/// Reject versioned transactions above the supported version, as getBlock would.
+fn check_transaction_versions(
+    block: &EncodedConfirmedBlock,
+    max_supported_transaction_version: Option<u8>,
+) -> Result<()> {
+    for (index, tx) in block.transactions.iter().enumerate() {
+        if let Some(TransactionVersion::Number(version)) = tx.version {
+            if max_supported_transaction_version.is_none_or(|max| version > max) {
+                return Err(anyhow!(
+                    "Transaction version {version} is not supported \
+                     (max supported: {max_supported_transaction_version:?}, transaction index: {index})"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
 #[async_trait]
 pub trait BlockProcessorTrait {
     async fn handle_block(&self, block_id: u64, block: EncodedConfirmedBlock) -> Result<()>;
@@ -26,38 +288,117 @@ pub trait BlockProcessorTrait {
         block: EncodedConfirmedBlock,
         entries: Vec<EntrySummary>,
     ) -> Result<()>;
+
+    /// Upload a block that is already in its stored form, skipping `convert_block`.
+    async fn handle_versioned_block_with_entries(
+        &self,
+        block_id: u64,
+        block: VersionedConfirmedBlockWithEntries,
+    ) -> Result<()>;
+
+    /// Whether handling a block looks at what was handled for its parent slot, so that blocks
+    /// must be handled one at a time in slot order.
+    fn checks_parent(&self) -> bool {
+        false
+    }
 }
 
 pub struct BlockProcessor {
     storage: LedgerStorage,
+    conversion_config: BlockConversionConfig,
+    sanity_rules: SanityRules,
+    signature_verifier: SignatureVerifier,
 }
 
 impl BlockProcessor {
     pub fn new(storage: LedgerStorage) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            conversion_config: BlockConversionConfig::default(),
+            sanity_rules: SanityRules::default(),
+            signature_verifier: SignatureVerifier::default(),
+        }
     }
 
-}
+    /// Convert blocks with the given options instead of the defaults.
+    pub fn with_conversion_config(mut self, conversion_config: BlockConversionConfig) -> Self {
+        self.conversion_config = conversion_config;
+        self
+    }
 
-#[async_trait]
-impl BlockProcessorTrait for BlockProcessor {
-    /// Takes a block ID and the `EncodedConfirmedBlock`, converts it, and uploads it.
-    async fn handle_block(&self, block_id: u64, block: EncodedConfirmedBlock) -> Result<()> {
-        let options = BlockEncodingOptions {
-            transaction_details: TransactionDetails::Full,
-            show_rewards: true,
-            max_supported_transaction_version: Some(0),
+    /// Check converted blocks against sanity rules before upload.
+    pub fn with_sanity_rules(mut self, sanity_rules: SanityRules) -> Self {
+        self.sanity_rules = sanity_rules;
+        self
+    }
+
+    /// Verify transaction signatures before upload, see `SignatureVerifier`.
+    pub fn with_signature_verifier(mut self, signature_verifier: SignatureVerifier) -> Self {
+        self.signature_verifier = signature_verifier;
+        self
+    }
+
+    /// Upload a block in its stored form, once it passed the sanity rules and its signatures are
+    /// verified, if enabled.
+    async fn upload(
+        &self,
+        block_id: u64,
+        block: ConfirmedBlock,
+        entries: Vec<EntrySummary>,
+        synthesized_meta_count: u64,
+        transactions_omitted: bool,
+    ) -> Result<()> {
+        self.sanity_rules.check_block(block_id, &block)?;
+
+        let block = if self.signature_verifier.is_enabled() {
+            let (block, invalid) = self.signature_verifier.verify_block(block).await?;
+            if let Some(first) = invalid.first() {
+                let message = format!(
+                    "Block={block_id} has {} transactions with invalid signatures, first: {} \
+                     (transaction index: {})",
+                    invalid.len(),
+                    first.signature,
+                    first.transaction_index
+                );
+                if self.signature_verifier.policy != SignatureVerificationPolicy::Quarantine {
+                    return Err(anyhow!(message));
+                }
+                warn!("{message}, quarantining it");
+                self.storage
+                    .quarantine_block(block_id, block)
+                    .await
+                    .context(format!("Failed to quarantine block={block_id}"))?;
+                return Ok(());
+            }
+            block
+        } else {
+            block
         };
-        let versioned_block = convert_block(block, UiTransactionEncoding::Json, options)
-            .map_err(|e| anyhow::anyhow!("Failed to convert block={block_id}: {}", e))?;
 
         self.storage
-            .upload_confirmed_block(block_id, versioned_block)
+            .upload_block_with_entries(
+                block_id,
+                block,
+                entries,
+                synthesized_meta_count,
+                transactions_omitted,
+            )
             .await
-            .context(format!("Failed to upload confirmed block={block_id}"))?;
+            .context(format!(
+                "Failed to upload confirmed block={block_id} with entries"
+            ))?;
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl BlockProcessorTrait for BlockProcessor {
+    /// Takes a block ID and the `EncodedConfirmedBlock`, converts it, and uploads it.
+    async fn handle_block(&self, block_id: u64, block: EncodedConfirmedBlock) -> Result<()> {
+        self.handle_block_with_entries(block_id, block, vec![])
+            .await
+    }
 
     /// Handle a block that already includes entries summaries. If the --write-block-entries flag is
     /// off, we still accept and upload the block, and ignore entries at storage layer.
@@ -67,26 +408,47 @@ impl BlockProcessorTrait for BlockProcessor {
         block: EncodedConfirmedBlock,
         entries: Vec<EntrySummary>,
     ) -> Result<()> {
-        let options = BlockEncodingOptions {
-            transaction_details: TransactionDetails::Full,
-            show_rewards: true,
-            max_supported_transaction_version: Some(0),
-        };
-        let versioned_block = convert_block(block, UiTransactionEncoding::Json, options)
-            .map_err(|e| anyhow::anyhow!("Failed to convert block={block_id}: {}", e))?;
+        // Checked before conversion, which may drop transactions
+        let entries = validate_block_entries(
+            block_id,
+            entries,
+            block.transactions.len(),
+            &block.blockhash,
+            self.conversion_config.entries_validation,
+        )?;
+        let ConvertedBlock {
+            block,
+            synthesized_meta_count,
+            transactions_omitted,
+        } = convert_encoded_block(block_id, block, &self.conversion_config)?;
 
-        let with_entries = VersionedConfirmedBlockWithEntries {
-            block: versioned_block,
+        self.upload(
+            block_id,
+            block,
             entries,
-        };
+            synthesized_meta_count,
+            transactions_omitted,
+        )
+        .await
+    }
 
-        self.storage
-            .upload_confirmed_block_with_entries(block_id, with_entries)
-            .await
-            .context(format!(
-                "Failed to upload confirmed block={block_id} with entries"
-            ))?;
+    async fn handle_versioned_block_with_entries(
+        &self,
+        block_id: u64,
+        block: VersionedConfirmedBlockWithEntries,
+    ) -> Result<()> {
+        let VersionedConfirmedBlockWithEntries { block, entries } = block;
+        let entries = validate_block_entries(
+            block_id,
+            entries,
+            block.transactions.len(),
+            &block.blockhash,
+            self.conversion_config.entries_validation,
+        )?;
+        self.upload(block_id, block.into(), entries, 0, false).await
+    }
 
-        Ok(())
+    fn checks_parent(&self) -> bool {
+        self.storage.checks_parent_chain() || self.sanity_rules.checks_parent()
     }
 }
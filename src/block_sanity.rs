@@ -0,0 +1,359 @@
+use {
+    anyhow::{anyhow, Result},
+    log::{info, warn},
+    solana_sdk::{clock::UnixTimestamp, pubkey::Pubkey, signature::Signature},
+    solana_transaction_status::{ConfirmedBlock, Reward, TransactionWithStatusMeta},
+    std::{
+        collections::{BTreeMap, HashSet},
+        fmt,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Block times before the mainnet-beta genesis, 2020-03-16, are out of bounds.
+pub const MIN_BLOCK_TIME: UnixTimestamp = 1_584_368_940;
+
+/// How far a block time may be ahead of the local clock.
+pub const MAX_BLOCK_TIME_SKEW_SECS: i64 = 60 * 60;
+
+/// Number of recent blocks kept to check block time and height against the parent.
+const RECENT_BLOCKS: usize = 1024;
+
+/// How a failed sanity rule is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SanityLevel {
+    /// Do not check the rule.
+    #[default]
+    Off,
+    /// Log a warning and store the block.
+    Warn,
+    /// Fail the whole block.
+    Error,
+}
+
+impl FromStr for SanityLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(SanityLevel::Off),
+            "warn" | "warning" => Ok(SanityLevel::Warn),
+            "error" => Ok(SanityLevel::Error),
+            other => Err(anyhow!("Unsupported sanity rule level: {other}")),
+        }
+    }
+}
+
+/// A sanity rule checked on converted blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SanityRule {
+    /// The parent slot is below the slot, except for the genesis block.
+    ParentSlot,
+    /// The block time is after genesis, not too far ahead of the local clock, and not before
+    /// the block time of the parent if the parent was seen recently.
+    BlockTime,
+    /// The block height is present, and one above the height of the parent if the parent was
+    /// seen recently.
+    BlockHeight,
+    /// No signature appears twice in the block.
+    DuplicateSignatures,
+    /// Pre and post balances have one entry per account key, loaded addresses included.
+    BalanceLengths,
+    /// Block and transaction reward pubkeys are valid base58 pubkeys.
+    RewardPubkeys,
+}
+
+impl SanityRule {
+    pub const ALL: [SanityRule; 6] = [
+        SanityRule::ParentSlot,
+        SanityRule::BlockTime,
+        SanityRule::BlockHeight,
+        SanityRule::DuplicateSignatures,
+        SanityRule::BalanceLengths,
+        SanityRule::RewardPubkeys,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SanityRule::ParentSlot => "parent_slot",
+            SanityRule::BlockTime => "block_time",
+            SanityRule::BlockHeight => "block_height",
+            SanityRule::DuplicateSignatures => "duplicate_signatures",
+            SanityRule::BalanceLengths => "balance_lengths",
+            SanityRule::RewardPubkeys => "reward_pubkeys",
+        }
+    }
+}
+
+impl fmt::Display for SanityRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SanityRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.trim().to_ascii_lowercase().replace('-', "_");
+        SanityRule::ALL
+            .into_iter()
+            .find(|rule| rule.name() == name)
+            .ok_or_else(|| anyhow!("Unknown sanity rule: {s}"))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RecentBlock {
+    block_time: Option<UnixTimestamp>,
+    block_height: Option<u64>,
+}
+
+/// Sanity rule levels, with counts of the violations found so far. Clones share the counts and
+/// the recent blocks.
+#[derive(Debug, Clone, Default)]
+pub struct SanityRules {
+    levels: [SanityLevel; SanityRule::ALL.len()],
+    counts: Arc<[AtomicU64; SanityRule::ALL.len()]>,
+    recent_blocks: Arc<Mutex<BTreeMap<u64, RecentBlock>>>,
+}
+
+impl SanityRules {
+    /// Check all rules at the given level.
+    pub fn new(level: SanityLevel) -> Self {
+        Self {
+            levels: [level; SanityRule::ALL.len()],
+            ..Self::default()
+        }
+    }
+
+    /// Check one rule at a different level.
+    pub fn with_rule_level(mut self, rule: SanityRule, level: SanityLevel) -> Self {
+        self.levels[rule as usize] = level;
+        self
+    }
+
+    pub fn level(&self, rule: SanityRule) -> SanityLevel {
+        self.levels[rule as usize]
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.levels.iter().any(|level| *level != SanityLevel::Off)
+    }
+
+    /// Whether a block is checked against its parent, which must be checked first.
+    pub fn checks_parent(&self) -> bool {
+        [SanityRule::BlockTime, SanityRule::BlockHeight]
+            .into_iter()
+            .any(|rule| self.level(rule) != SanityLevel::Off)
+    }
+
+    /// How many blocks failed each rule since these rules were created.
+    pub fn violation_counts(&self) -> Vec<(SanityRule, u64)> {
+        SanityRule::ALL
+            .iter()
+            .map(|rule| (*rule, self.counts[*rule as usize].load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Log the violation counts every `interval` while they keep changing. Never returns, spawn
+    /// it next to the ingestor.
+    pub async fn report_violations(self, interval: Duration) {
+        let mut reported = self.violation_counts();
+        loop {
+            tokio::time::sleep(interval).await;
+            let counts = self.violation_counts();
+            if counts == reported {
+                continue;
+            }
+            let summary = counts
+                .iter()
+                .filter(|(_, count)| *count > 0)
+                .map(|(rule, count)| format!("{rule}={count}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            info!("Sanity rule violations so far: {summary}");
+            reported = counts;
+        }
+    }
+
+    /// Check a converted block. Violations of warning rules are logged, violations of error
+    /// rules fail the block; all are counted.
+    pub fn check_block(&self, slot: u64, block: &ConfirmedBlock) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let parent = self
+            .recent_blocks
+            .lock()
+            .unwrap()
+            .get(&block.parent_slot)
+            .copied();
+
+        let mut errors = vec![];
+        for rule in SanityRule::ALL {
+            let level = self.level(rule);
+            if level == SanityLevel::Off {
+                continue;
+            }
+            let Some(violation) = check_rule(rule, slot, block, parent.as_ref()) else {
+                continue;
+            };
+            self.counts[rule as usize].fetch_add(1, Ordering::Relaxed);
+            match level {
+                SanityLevel::Off => {}
+                SanityLevel::Warn => {
+                    warn!("Sanity rule {rule} failed for block slot={slot}: {violation}")
+                }
+                SanityLevel::Error => errors.push(format!("{rule}: {violation}")),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(anyhow!(
+                "Block slot={slot} failed sanity rules: {}",
+                errors.join("; ")
+            ));
+        }
+
+        let mut recent_blocks = self.recent_blocks.lock().unwrap();
+        recent_blocks.insert(
+            slot,
+            RecentBlock {
+                block_time: block.block_time,
+                block_height: block.block_height,
+            },
+        );
+        while recent_blocks.len() > RECENT_BLOCKS {
+            recent_blocks.pop_first();
+        }
+        Ok(())
+    }
+}
+
+/// Describe how a block violates a rule, if it does.
+fn check_rule(
+    rule: SanityRule,
+    slot: u64,
+    block: &ConfirmedBlock,
+    parent: Option<&RecentBlock>,
+) -> Option<String> {
+    match rule {
+        SanityRule::ParentSlot => (slot > 0 && block.parent_slot >= slot)
+            .then(|| format!("parent slot {} is not below the slot", block.parent_slot)),
+        SanityRule::BlockTime => {
+            let block_time = block.block_time?;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs() as i64)
+                .unwrap_or_default();
+            if block_time < MIN_BLOCK_TIME {
+                return Some(format!("block time {block_time} is before genesis"));
+            }
+            if block_time > now + MAX_BLOCK_TIME_SKEW_SECS {
+                return Some(format!(
+                    "block time {block_time} is more than {MAX_BLOCK_TIME_SKEW_SECS}s ahead of \
+                     the local clock"
+                ));
+            }
+            let parent_time = parent.and_then(|parent| parent.block_time)?;
+            (block_time < parent_time).then(|| {
+                format!(
+                    "block time {block_time} is before block time {parent_time} of parent slot {}",
+                    block.parent_slot
+                )
+            })
+        }
+        SanityRule::BlockHeight => {
+            let Some(block_height) = block.block_height else {
+                return Some("block height is missing".to_string());
+            };
+            let parent_height = parent.and_then(|parent| parent.block_height)?;
+            (block_height != parent_height + 1).then(|| {
+                format!(
+                    "block height {block_height} does not follow block height {parent_height} \
+                     of parent slot {}",
+                    block.parent_slot
+                )
+            })
+        }
+        SanityRule::DuplicateSignatures => {
+            let mut seen = HashSet::new();
+            let duplicates: Vec<_> = block
+                .transactions
+                .iter()
+                .flat_map(transaction_signatures)
+                .filter(|signature| !seen.insert(*signature))
+                .collect();
+            duplicates
+                .first()
+                .map(|first| format!("{} duplicate signatures, first: {first}", duplicates.len()))
+        }
+        SanityRule::BalanceLengths => {
+            let mismatched: Vec<_> = block
+                .transactions
+                .iter()
+                .enumerate()
+                .filter_map(|(index, transaction)| match transaction {
+                    TransactionWithStatusMeta::Complete(transaction) => {
+                        let keys = transaction.account_keys().len();
+                        let meta = &transaction.meta;
+                        (meta.pre_balances.len() != keys || meta.post_balances.len() != keys).then(
+                            || {
+                                format!(
+                                    "transaction index {index} has {keys} account keys, {} pre \
+                                     balances and {} post balances",
+                                    meta.pre_balances.len(),
+                                    meta.post_balances.len()
+                                )
+                            },
+                        )
+                    }
+                    TransactionWithStatusMeta::MissingMetadata(_) => None,
+                })
+                .collect();
+            mismatched.first().map(|first| {
+                format!(
+                    "{} transactions with mismatched balances, first: {first}",
+                    mismatched.len()
+                )
+            })
+        }
+        SanityRule::RewardPubkeys => {
+            let transaction_rewards =
+                block
+                    .transactions
+                    .iter()
+                    .flat_map(|transaction| match transaction {
+                        TransactionWithStatusMeta::Complete(transaction) => {
+                            transaction.meta.rewards.as_deref().unwrap_or_default()
+                        }
+                        TransactionWithStatusMeta::MissingMetadata(_) => &[],
+                    });
+            let invalid: Vec<&Reward> = block
+                .rewards
+                .iter()
+                .chain(transaction_rewards)
+                .filter(|reward| Pubkey::from_str(&reward.pubkey).is_err())
+                .collect();
+            invalid.first().map(|first| {
+                format!(
+                    "{} rewards with invalid pubkeys, first: {:?}",
+                    invalid.len(),
+                    first.pubkey
+                )
+            })
+        }
+    }
+}
+
+fn transaction_signatures(transaction: &TransactionWithStatusMeta) -> &[Signature] {
+    match transaction {
+        TransactionWithStatusMeta::Complete(transaction) => &transaction.transaction.signatures,
+        TransactionWithStatusMeta::MissingMetadata(transaction) => &transaction.signatures,
+    }
+}
@@ -0,0 +1,206 @@
+use {
+    anyhow::{anyhow, Result},
+    sha2::{Digest, Sha256},
+    std::{
+        fmt,
+        pin::Pin,
+        str::FromStr,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    },
+    tokio::io::{AsyncRead, ReadBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub const ALL: [ChecksumAlgorithm; 2] = [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Md5];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Md5 => "md5",
+            ChecksumAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// Suffix of the sidecar file holding the checksum of `<file>`, e.g. `<file>.sha256`.
+    pub fn sidecar_suffix(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Md5 => ".md5",
+            ChecksumAlgorithm::Sha256 => ".sha256",
+        }
+    }
+
+    fn hex_len(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Md5 => 32,
+            ChecksumAlgorithm::Sha256 => 64,
+        }
+    }
+}
+
+/// A checksum the raw contents of a file are expected to match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedChecksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub hex: String,
+}
+
+impl ExpectedChecksum {
+    pub fn new(algorithm: ChecksumAlgorithm, hex: &str) -> Result<Self> {
+        let hex = hex.trim().to_ascii_lowercase();
+        if hex.len() != algorithm.hex_len() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!("Invalid {} checksum: {hex}", algorithm.name()));
+        }
+        Ok(Self { algorithm, hex })
+    }
+
+    /// Parse the contents of a `md5sum`/`sha256sum` style sidecar: `<hex>  <file name>`.
+    pub fn from_sidecar(algorithm: ChecksumAlgorithm, contents: &str) -> Result<Self> {
+        let hex = contents
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("Empty {} checksum file", algorithm.name()))?;
+        Self::new(algorithm, hex)
+    }
+}
+
+impl FromStr for ExpectedChecksum {
+    type Err = anyhow::Error;
+
+    /// Parse `<algorithm>:<hex>`, e.g. `sha256:9f86d08...`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, hex) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Checksum must be formatted as <algorithm>:<hex>: {s}"))?;
+        let algorithm = match name.trim().to_ascii_lowercase().as_str() {
+            "md5" => ChecksumAlgorithm::Md5,
+            "sha256" => ChecksumAlgorithm::Sha256,
+            other => return Err(anyhow!("Unsupported checksum algorithm: {other}")),
+        };
+        Self::new(algorithm, hex)
+    }
+}
+
+impl fmt::Display for ExpectedChecksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.hex)
+    }
+}
+
+#[derive(Clone)]
+enum Hasher {
+    Md5(md5::Context),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => Hasher::Md5(md5::Context::new()),
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    fn algorithm(&self) -> ChecksumAlgorithm {
+        match self {
+            Hasher::Md5(_) => ChecksumAlgorithm::Md5,
+            Hasher::Sha256(_) => ChecksumAlgorithm::Sha256,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(context) => context.consume(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    fn hex_digest(self) -> String {
+        match self {
+            Hasher::Md5(context) => format!("{:x}", context.compute()),
+            Hasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+        }
+    }
+}
+
+/// An `AsyncRead` adapter that computes digests of all bytes read through it.
+pub struct ChecksumReader {
+    inner: Box<dyn AsyncRead + Unpin + Send>,
+    hashers: Arc<Mutex<Vec<Hasher>>>,
+}
+
+/// Gives access to the digests after the reader has been handed off to the decompressor.
+#[derive(Clone)]
+pub struct ChecksumHandle {
+    hashers: Arc<Mutex<Vec<Hasher>>>,
+}
+
+impl ChecksumReader {
+    pub fn new(
+        inner: Box<dyn AsyncRead + Unpin + Send>,
+        algorithms: &[ChecksumAlgorithm],
+    ) -> (Self, ChecksumHandle) {
+        let mut hashers: Vec<Hasher> = vec![];
+        for algorithm in algorithms {
+            if !hashers.iter().any(|h| h.algorithm() == *algorithm) {
+                hashers.push(Hasher::new(*algorithm));
+            }
+        }
+        let hashers = Arc::new(Mutex::new(hashers));
+        let handle = ChecksumHandle {
+            hashers: hashers.clone(),
+        };
+        (Self { inner, hashers }, handle)
+    }
+}
+
+impl ChecksumHandle {
+    /// Hex digest of the bytes read so far, if the algorithm was requested.
+    pub fn hex_digest(&self, algorithm: ChecksumAlgorithm) -> Option<String> {
+        self.hashers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|h| h.algorithm() == algorithm)
+            .map(|h| h.clone().hex_digest())
+    }
+
+    /// Compare the digest of the bytes read so far against an expected checksum.
+    pub fn verify(&self, expected: &ExpectedChecksum) -> Result<()> {
+        let actual = self
+            .hex_digest(expected.algorithm)
+            .ok_or_else(|| anyhow!("{} checksum was not computed", expected.algorithm.name()))?;
+        if actual != expected.hex {
+            return Err(anyhow!(
+                "Checksum mismatch: expected {expected}, got {}:{actual}",
+                expected.algorithm.name()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl AsyncRead for ChecksumReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled_before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            let read = &buf.filled()[filled_before..];
+            if !read.is_empty() {
+                for hasher in self.hashers.lock().unwrap().iter_mut() {
+                    hasher.update(read);
+                }
+            }
+        }
+        poll
+    }
+}
@@ -1,8 +1,24 @@
-use crate::ledger_storage::{FilterTxIncludeExclude, LedgerCacheConfig, UploaderConfig};
+use crate::{
+    block_processor::{BlockConversionConfig, EntriesValidationPolicy, MissingMetadataPolicy},
+    block_sanity::{SanityLevel, SanityRule, SanityRules},
+    file_lifecycle::{FileLifecycleConfig, SuccessAction, DEFAULT_DONE_DIR, DEFAULT_FAILED_DIR},
+    file_registry::FILE_REGISTRY_TABLE_NAME,
+    gap_detector::{GapDetectorConfig, DEFAULT_MISSING_SLOTS_TOPIC},
+    ledger_storage::{
+        FilterTxIncludeExclude, LedgerCacheConfig, ParentChainPolicy, UploaderConfig,
+        QUARANTINE_TABLE_NAME,
+    },
+    message_decoder::DEFAULT_MAX_DECOMPRESSED_MESSAGE_SIZE,
+    schema_compat::{SchemaCompat, SchemaCompatMode},
+    signature_verification::{
+        default_verification_threads, SignatureVerificationPolicy, SignatureVerifier,
+    },
+};
 use {
     clap::{value_t_or_exit, values_t, App, Arg, ArgMatches},
     solana_clap_utils::input_validators::{is_parsable, is_pubkey, is_within_range},
-    solana_sdk::pubkey::Pubkey,
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    solana_transaction_status::TransactionDetails,
 };
 
 const EXCLUDE_TX_FULL_ADDR: &str = "filter-tx-full-exclude-addr";
@@ -19,7 +35,72 @@ pub fn block_uploader_app<'a>(version: &'a str) -> App<'a, 'a> {
             Arg::with_name("add_empty_tx_metadata_if_missing")
                 .long("add-empty-tx-metadata-if-missing")
                 .takes_value(false)
-                .help("Add empty transaction metadata if it is missing in input"),
+                .conflicts_with("missing_tx_metadata")
+                .help("Add empty transaction metadata if it is missing in input. \
+                       Same as --missing-tx-metadata synthesize."),
+        )
+        .arg(
+            Arg::with_name("missing_tx_metadata")
+                .long("missing-tx-metadata")
+                .value_name("POLICY")
+                .takes_value(true)
+                .possible_values(&["reject", "synthesize", "store"])
+                .help("Handling of transactions without status metadata: reject the block, \
+                       synthesize empty metadata, or store the transaction without metadata. \
+                       Affected blocks are counted in indexing progress. [default: reject]"),
+        )
+        .arg(
+            Arg::with_name("entries_validation")
+                .long("entries-validation")
+                .value_name("POLICY")
+                .takes_value(true)
+                .possible_values(&["off", "reject", "warn", "store-without-entries"])
+                .help("Check that entries match their block: contiguous starting transaction \
+                       indexes, transaction counts adding up to the block's, and a last entry \
+                       hash equal to the blockhash. Inconsistent entries reject the block, are \
+                       stored with a warning, or are left out of the stored block. [default: off]"),
+        )
+        .arg(
+            Arg::with_name("sanity_checks")
+                .long("sanity-checks")
+                .value_name("LEVEL")
+                .takes_value(true)
+                .possible_values(&["off", "warn", "error"])
+                .help("Check converted blocks against all sanity rules: parent_slot, block_time, \
+                       block_height, duplicate_signatures, balance_lengths and reward_pubkeys. \
+                       Violations are logged as warnings or fail the block, and the service \
+                       logs their counts every minute. [default: off]"),
+        )
+        .arg(
+            Arg::with_name("sanity_rule")
+                .long("sanity-rule")
+                .value_name("RULE=LEVEL")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(is_sanity_rule_level)
+                .help("Check one sanity rule at its own level, e.g. block_time=warn. \
+                       May be repeated and overrides --sanity-checks."),
+        )
+        .arg(
+            Arg::with_name("verify_signatures")
+                .long("verify-signatures")
+                .value_name("POLICY")
+                .takes_value(true)
+                .possible_values(&["off", "reject", "quarantine"])
+                .help("Verify the ed25519 signatures of all transactions against their messages \
+                       before upload. Blocks with invalid signatures are rejected, or stored in \
+                       the quarantine table only. [default: off]"),
+        )
+        .arg(
+            Arg::with_name("signature_verification_threads")
+                .long("signature-verification-threads")
+                .value_name("N")
+                .takes_value(true)
+                .requires("verify_signatures")
+                .validator(is_thread_count)
+                .help("Threads verifying signatures, shared by all blocks \
+                       [default: number of CPUs]"),
         )
         .arg(
             Arg::with_name("write_block_entries")
@@ -33,6 +114,23 @@ pub fn block_uploader_app<'a>(version: &'a str) -> App<'a, 'a> {
                 .takes_value(false)
                 .help("Validate/parse stdin JSON only; do not connect to HBase/HDFS or write anything."),
         )
+        .arg(
+            Arg::with_name("validate_report")
+                .long("validate-report")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .help("Report format of --validate-only and --dry-run: text on stderr, or one \
+                       JSON line per block on stdout followed by a summary line [default: text]"),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .takes_value(false)
+                .help("Validate stdin JSON and run the full upload of each block without writing \
+                       anything; report the rows and compressed bytes per table and the \
+                       transactions dropped by each filter."),
+        )
         .arg(
             Arg::with_name("disable_tx")
                 .long("disable-tx")
@@ -242,9 +340,364 @@ pub fn block_uploader_app<'a>(version: &'a str) -> App<'a, 'a> {
                 .takes_value(false)
                 .help("Disable filters for first/last transactions of blocks in `tx` table."),
         )
+        .arg(
+            Arg::with_name("move_done_files")
+                .long("move-done-files")
+                .takes_value(false)
+                .help("Move successfully ingested files into the done directory."),
+        )
+        .arg(
+            Arg::with_name("delete_done_files")
+                .long("delete-done-files")
+                .takes_value(false)
+                .conflicts_with("move_done_files")
+                .help("Delete successfully ingested files."),
+        )
+        .arg(
+            Arg::with_name("done_dir")
+                .long("done-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .requires("move_done_files")
+                .help("Directory for successfully ingested files. Relative paths resolve against \
+                       the directory of each file [default: done]"),
+        )
+        .arg(
+            Arg::with_name("move_failed_files")
+                .long("move-failed-files")
+                .takes_value(false)
+                .help("Move files that failed to ingest into the failed directory, \
+                       together with an .error.json report."),
+        )
+        .arg(
+            Arg::with_name("failed_dir")
+                .long("failed-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .requires("move_failed_files")
+                .help("Directory for files that failed to ingest. Relative paths resolve against \
+                       the directory of each file [default: failed]"),
+        )
+        .arg(
+            Arg::with_name("parent_chain_check")
+                .long("parent-chain-check")
+                .value_name("POLICY")
+                .takes_value(true)
+                .possible_values(&["off", "reject", "overwrite", "quarantine"])
+                .help("Compare the previous blockhash of each block with the blockhash stored in \
+                       indexing progress for its parent slot. Blocks that do not chain, from a \
+                       minority fork or corrupt, are rejected, uploaded anyway, or stored in the \
+                       quarantine table only. [default: off]"),
+        )
+        .arg(
+            Arg::with_name("quarantine_table")
+                .long("quarantine-table")
+                .value_name("TABLE")
+                .takes_value(true)
+                .help("HBase table for blocks quarantined by --parent-chain-check or \
+                       --verify-signatures [default: quarantined_blocks]"),
+        )
+        .arg(
+            Arg::with_name("enable_file_registry")
+                .long("enable-file-registry")
+                .takes_value(false)
+                .help("Track ingested files in HBase and skip files that were already ingested."),
+        )
+        .arg(
+            Arg::with_name("file_registry_table")
+                .long("file-registry-table")
+                .value_name("TABLE")
+                .takes_value(true)
+                .requires("enable_file_registry")
+                .help("HBase table used for the file registry [default: ingestor_file_registry]"),
+        )
+        .arg(
+            Arg::with_name("verify_checksum_sidecars")
+                .long("verify-checksum-sidecars")
+                .takes_value(false)
+                .help("Verify input files against <file>.sha256 or <file>.md5 checksum files."),
+        )
+        .arg(
+            Arg::with_name("require_checksum")
+                .long("require-checksum")
+                .takes_value(false)
+                .help("Fail input files that come without a checksum in the message or a checksum file."),
+        )
+        .arg(
+            Arg::with_name("split_files")
+                .long("split-files")
+                .value_name("N")
+                .validator(is_split_count)
+                .takes_value(true)
+                .help("Coordinator mode: split uncompressed input files into N byte-range \
+                       messages instead of processing them, so several ingestors can share a file."),
+        )
+        .arg(
+            Arg::with_name("split_topic")
+                .long("split-topic")
+                .value_name("TOPIC")
+                .takes_value(true)
+                .requires("split_files")
+                .help("Kafka topic for byte-range messages [default: the consume topic]"),
+        )
+        .arg(
+            Arg::with_name("force_reprocess_files")
+                .long("force-reprocess-files")
+                .takes_value(false)
+                .help("Process files even if the file registry marks them, or a file with the \
+                       same contents, as ingested."),
+        )
+        .arg(
+            Arg::with_name("max_decompressed_message_size")
+                .long("max-decompressed-message-size")
+                .value_name("BYTES")
+                .validator(is_message_size)
+                .takes_value(true)
+                .help("Maximum size of a gzip or zstd compressed message payload after \
+                       decompression [default: 268435456]"),
+        )
+        .arg(
+            Arg::with_name("message_format")
+                .long("message-format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["json", "protobuf", "confluent"])
+                .default_value("json")
+                .help("Format of Kafka messages without a payload-type header: JSON block \
+                       envelopes and file paths, ConfirmedBlock protobuf messages keyed by a \
+                       slot header, or Confluent Schema Registry wire format. Messages with the \
+                       header are decoded as json, file-path, protobuf-block or confluent."),
+        )
+        .arg(
+            Arg::with_name("schema_registry_url")
+                .long("schema-registry-url")
+                .value_name("URL")
+                .takes_value(true)
+                .help("Confluent Schema Registry used to resolve schema IDs of confluent messages"),
+        )
+        .arg(
+            Arg::with_name("schema_cache_dir")
+                .long("schema-cache-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .help("Directory caching schemas as <id>.json files. Cached schemas are used \
+                       without contacting the registry."),
+        )
+        .arg(
+            Arg::with_name("transaction_details")
+                .long("transaction-details")
+                .value_name("DETAILS")
+                .takes_value(true)
+                .possible_values(&["full", "none"])
+                .default_value("full")
+                .help("Transactions stored with each block: all of them, or none for blocks \
+                       fetched with transactionDetails=none. Blocks stored without transactions \
+                       are marked as such next to their indexing progress"),
+        )
+        .arg(
+            Arg::with_name("omit_rewards")
+                .long("omit-rewards")
+                .takes_value(false)
+                .help("Store blocks without rewards. Needed for blocks fetched with rewards=false."),
+        )
+        .arg(
+            Arg::with_name("max_supported_transaction_version")
+                .long("max-supported-transaction-version")
+                .value_name("VERSION")
+                .takes_value(true)
+                .validator(is_transaction_version)
+                .default_value("0")
+                .help("Highest transaction version accepted in a block, or \"legacy\" to accept \
+                       legacy transactions only"),
+        )
+        .arg(
+            Arg::with_name("schema_compat")
+                .long("schema-compat")
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(&["off", "lenient", "strict"])
+                .default_value("lenient")
+                .help("Handling of block JSON that drifted from the expected schema, such as \
+                       unknown fields, snake_case keys or numbers given as strings: decode \
+                       blocks as they are, normalise blocks that fail to decode or have keys \
+                       decoding would ignore and retry, or fail every block that needs \
+                       normalising"),
+        )
     ;
 }
 
+pub fn gap_detector_app<'a>(version: &'a str) -> App<'a, 'a> {
+    App::new("solana-block-gap-detector")
+        .about("Reports slots missing from indexing progress and publishes them for backfill")
+        .version(version)
+        .arg(
+            Arg::with_name("start_slot")
+                .long("start-slot")
+                .value_name("SLOT")
+                .takes_value(true)
+                .required(true)
+                .validator(is_slot)
+                .help("First slot of the range to scan"),
+        )
+        .arg(
+            Arg::with_name("end_slot")
+                .long("end-slot")
+                .value_name("SLOT")
+                .takes_value(true)
+                .required(true)
+                .validator(is_slot)
+                .help("Last slot of the range to scan, included"),
+        )
+        .arg(
+            Arg::with_name("use_parent_links")
+                .long("use-parent-links")
+                .takes_value(false)
+                .help("Use the parent slots of stored blocks to tell skipped slots from missing \
+                       ones. Slots that stored blocks skip over are not reported, slots that \
+                       are the parent of a stored block are reported as parent_of_stored_block, \
+                       and other slots as unlinked."),
+        )
+        .arg(
+            Arg::with_name("missing_slots_topic")
+                .long("missing-slots-topic")
+                .value_name("TOPIC")
+                .takes_value(true)
+                .conflicts_with("no_publish")
+                .help("Kafka topic to publish missing slots to \
+                       [default: sol.missing.blocks]"),
+        )
+        .arg(
+            Arg::with_name("no_publish")
+                .long("no-publish")
+                .takes_value(false)
+                .help("Only report missing slots, do not publish them"),
+        )
+}
+
+/// Process the maximum decompressed size of compressed message payloads
+pub fn process_max_decompressed_message_size(matches: &ArgMatches) -> u64 {
+    if matches.is_present("max_decompressed_message_size") {
+        value_t_or_exit!(matches, "max_decompressed_message_size", u64)
+    } else {
+        DEFAULT_MAX_DECOMPRESSED_MESSAGE_SIZE
+    }
+}
+
+/// Process block conversion CLI arguments
+pub fn process_block_conversion_arguments(matches: &ArgMatches) -> BlockConversionConfig {
+    let transaction_details = match matches.value_of("transaction_details") {
+        Some("none") => TransactionDetails::None,
+        _ => TransactionDetails::Full,
+    };
+    let max_supported_transaction_version = match matches.value_of("max_supported_transaction_version") {
+        Some("legacy") => None,
+        Some(_) => Some(value_t_or_exit!(matches, "max_supported_transaction_version", u8)),
+        None => BlockConversionConfig::default().max_supported_transaction_version,
+    };
+
+    let missing_metadata = if matches.is_present("add_empty_tx_metadata_if_missing") {
+        MissingMetadataPolicy::Synthesize
+    } else if matches.is_present("missing_tx_metadata") {
+        value_t_or_exit!(matches, "missing_tx_metadata", MissingMetadataPolicy)
+    } else {
+        MissingMetadataPolicy::default()
+    };
+
+    let entries_validation = if matches.is_present("entries_validation") {
+        value_t_or_exit!(matches, "entries_validation", EntriesValidationPolicy)
+    } else {
+        EntriesValidationPolicy::default()
+    };
+
+    BlockConversionConfig {
+        transaction_details,
+        show_rewards: !matches.is_present("omit_rewards"),
+        max_supported_transaction_version,
+        missing_metadata,
+        entries_validation,
+    }
+}
+
+/// Process the schema compatibility mode
+pub fn process_schema_compat_arguments(matches: &ArgMatches) -> SchemaCompat {
+    SchemaCompat::new(value_t_or_exit!(matches, "schema_compat", SchemaCompatMode))
+}
+
+/// Process sanity rule CLI arguments
+pub fn process_sanity_rules_arguments(matches: &ArgMatches) -> SanityRules {
+    let level = if matches.is_present("sanity_checks") {
+        value_t_or_exit!(matches, "sanity_checks", SanityLevel)
+    } else {
+        SanityLevel::default()
+    };
+    matches
+        .values_of("sanity_rule")
+        .into_iter()
+        .flatten()
+        .filter_map(|rule_level| parse_sanity_rule_level(rule_level).ok())
+        .fold(SanityRules::new(level), |rules, (rule, level)| {
+            rules.with_rule_level(rule, level)
+        })
+}
+
+fn parse_sanity_rule_level(rule_level: &str) -> anyhow::Result<(SanityRule, SanityLevel)> {
+    let (rule, level) = rule_level
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Expected RULE=LEVEL, got {rule_level}"))?;
+    Ok((rule.parse()?, level.parse()?))
+}
+
+fn is_sanity_rule_level(rule_level: String) -> Result<(), String> {
+    parse_sanity_rule_level(&rule_level)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Process signature verification CLI arguments
+pub fn process_signature_verification_arguments(
+    matches: &ArgMatches,
+) -> anyhow::Result<SignatureVerifier> {
+    let policy = if matches.is_present("verify_signatures") {
+        value_t_or_exit!(matches, "verify_signatures", SignatureVerificationPolicy)
+    } else {
+        SignatureVerificationPolicy::default()
+    };
+    let threads = matches
+        .value_of("signature_verification_threads")
+        .and_then(|threads| threads.parse().ok())
+        .unwrap_or_else(default_verification_threads);
+    SignatureVerifier::new(policy, threads)
+}
+
+fn is_thread_count(threads: String) -> Result<(), String> {
+    match threads.parse::<usize>() {
+        Ok(threads) if threads > 0 => Ok(()),
+        _ => Err(format!("Invalid thread count: {threads}")),
+    }
+}
+
+fn is_split_count(splits: String) -> Result<(), String> {
+    match splits.parse::<u64>() {
+        Ok(splits) if splits > 0 => Ok(()),
+        _ => Err(format!("Invalid split count: {splits}")),
+    }
+}
+
+fn is_message_size(size: String) -> Result<(), String> {
+    match size.parse::<u64>() {
+        Ok(size) if size > 0 => Ok(()),
+        _ => Err(format!("Invalid message size: {size}")),
+    }
+}
+
+fn is_transaction_version(version: String) -> Result<(), String> {
+    if version == "legacy" || version.parse::<u8>().is_ok() {
+        Ok(())
+    } else {
+        Err(format!("Invalid transaction version: {version}"))
+    }
+}
+
 /// Process uploader-related CLI arguments
 pub fn process_uploader_arguments(matches: &ArgMatches) -> UploaderConfig {
     let write_block_entries = matches.is_present("write_block_entries");
@@ -271,6 +724,15 @@ pub fn process_uploader_arguments(matches: &ArgMatches) -> UploaderConfig {
     let use_tx_full_compression = !matches.is_present("disable_tx_full_compression");
     let hbase_write_to_wal = !matches.is_present("hbase_skip_wal");
     let disable_tx_filter_block_boundary = matches.is_present("disable_tx_filter_block_boundary");
+    let parent_chain_policy = if matches.is_present("parent_chain_check") {
+        value_t_or_exit!(matches, "parent_chain_check", ParentChainPolicy)
+    } else {
+        ParentChainPolicy::default()
+    };
+    let quarantine_table_name = matches
+        .value_of("quarantine_table")
+        .unwrap_or(QUARANTINE_TABLE_NAME)
+        .to_string();
 
     let filter_tx_full_include_addrs: std::collections::HashSet<Pubkey> =
         values_t!(matches, "filter_tx_full_include_addr", Pubkey)
@@ -333,6 +795,8 @@ pub fn process_uploader_arguments(matches: &ArgMatches) -> UploaderConfig {
         use_tx_full_compression,
         hbase_write_to_wal,
         disable_tx_filter_block_boundary,
+        parent_chain_policy,
+        quarantine_table_name,
         ..Default::default()
     }
 }
@@ -374,6 +838,69 @@ pub fn process_cache_arguments(matches: &ArgMatches) -> LedgerCacheConfig {
     }
 }
 
+/// Process file post-processing CLI arguments
+pub fn process_file_lifecycle_arguments(matches: &ArgMatches) -> FileLifecycleConfig {
+    let on_success = if matches.is_present("delete_done_files") {
+        SuccessAction::Delete
+    } else if matches.is_present("move_done_files") {
+        SuccessAction::MoveTo(
+            matches
+                .value_of("done_dir")
+                .unwrap_or(DEFAULT_DONE_DIR)
+                .to_string(),
+        )
+    } else {
+        SuccessAction::Keep
+    };
+
+    let failed_dir = matches.is_present("move_failed_files").then(|| {
+        matches
+            .value_of("failed_dir")
+            .unwrap_or(DEFAULT_FAILED_DIR)
+            .to_string()
+    });
+
+    FileLifecycleConfig {
+        on_success,
+        failed_dir,
+    }
+}
+
+/// Process file registry CLI arguments. Returns the registry table name if the registry is enabled.
+pub fn process_file_registry_arguments(matches: &ArgMatches) -> Option<String> {
+    matches.is_present("enable_file_registry").then(|| {
+        matches
+            .value_of("file_registry_table")
+            .unwrap_or(FILE_REGISTRY_TABLE_NAME)
+            .to_string()
+    })
+}
+
+/// Process gap detector CLI arguments
+pub fn process_gap_detector_arguments(matches: &ArgMatches) -> GapDetectorConfig {
+    GapDetectorConfig {
+        start_slot: value_t_or_exit!(matches, "start_slot", Slot),
+        end_slot: value_t_or_exit!(matches, "end_slot", Slot),
+        use_parent_links: matches.is_present("use_parent_links"),
+    }
+}
+
+/// Topic to publish missing slots to, or `None` if they are only reported
+pub fn process_missing_slots_topic(matches: &ArgMatches) -> Option<String> {
+    (!matches.is_present("no_publish")).then(|| {
+        matches
+            .value_of("missing_slots_topic")
+            .unwrap_or(DEFAULT_MISSING_SLOTS_TOPIC)
+            .to_string()
+    })
+}
+
+fn is_slot(slot: String) -> Result<(), String> {
+    slot.parse::<Slot>()
+        .map(|_| ())
+        .map_err(|_| format!("Invalid slot: {slot}"))
+}
+
 /// Helper function to create a filter
 fn create_filter(
     filter_tx_exclude_addrs: std::collections::HashSet<Pubkey>,
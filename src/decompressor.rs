@@ -1,8 +1,13 @@
 use {
-    async_compression::tokio::bufread::GzipDecoder,
-    tokio::io::{AsyncRead, BufReader},
+    anyhow::{anyhow, Result},
+    async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder},
+    std::{io::Cursor, str::FromStr},
+    tokio::io::{AsyncRead, AsyncReadExt, BufReader},
 };
 
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
 pub trait Decompressor: Send + Sync {
     fn decompress(
         &self,
@@ -24,6 +29,17 @@ impl Decompressor for GzipDecompressor {
     }
 }
 
+pub struct ZstdDecompressor;
+
+impl Decompressor for ZstdDecompressor {
+    fn decompress(
+        &self,
+        input: Box<dyn AsyncRead + Unpin + Send>,
+    ) -> Box<dyn AsyncRead + Unpin + Send> {
+        Box::new(ZstdDecoder::new(BufReader::new(input)))
+    }
+}
+
 pub struct NoOpDecompressor;
 
 impl Decompressor for NoOpDecompressor {
@@ -34,3 +50,67 @@ impl Decompressor for NoOpDecompressor {
         input
     }
 }
+
+/// Compression codecs supported for input data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn decompressor(&self) -> Box<dyn Decompressor + Send + Sync> {
+        match self {
+            Compression::None => Box::new(NoOpDecompressor),
+            Compression::Gzip => Box::new(GzipDecompressor),
+            Compression::Zstd => Box::new(ZstdDecompressor),
+        }
+    }
+
+    /// Detect the compression of an in-memory payload from its magic bytes.
+    pub fn detect(data: &[u8]) -> Compression {
+        if data.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if data.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Decompress an in-memory payload, failing if the output would exceed `max_size` bytes.
+pub async fn decompress_bytes(
+    compression: Compression,
+    data: &[u8],
+    max_size: u64,
+) -> Result<Vec<u8>> {
+    let input: Box<dyn AsyncRead + Unpin + Send> = Box::new(Cursor::new(data.to_vec()));
+    let mut reader = compression.decompressor().decompress(input).take(max_size + 1);
+    let mut output = Vec::new();
+    reader
+        .read_to_end(&mut output)
+        .await
+        .map_err(|e| anyhow!("Failed to decompress {compression:?} payload: {e}"))?;
+    if output.len() as u64 > max_size {
+        return Err(anyhow!(
+            "Decompressed {compression:?} payload exceeds the limit of {max_size} bytes"
+        ));
+    }
+    Ok(output)
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "none" | "identity" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            other => Err(anyhow!("Unsupported compression: {other}")),
+        }
+    }
+}
@@ -4,7 +4,7 @@ use {
     solana_hash::Hash,
     solana_transaction_status::EntrySummary,
 };
-use crate::json_utils::from_value_with_path;
+use crate::json_utils::deserialize_with_path;
 
 #[derive(serde::Deserialize)]
 struct JsonEntrySummary {
@@ -43,7 +43,7 @@ pub fn parse_entries_from_value(v: &Value) -> Result<Vec<EntrySummary>> {
 }
 
 pub fn parse_entry_summary(v: &Value) -> Result<EntrySummary> {
-    let je: JsonEntrySummary = from_value_with_path(v.clone(), "EntrySummary")
+    let je: JsonEntrySummary = deserialize_with_path(v, "EntrySummary")
         .with_context(|| "Invalid entry summary object")?;
     let hash: Hash = je
         .hash
@@ -56,3 +56,41 @@ pub fn parse_entry_summary(v: &Value) -> Result<EntrySummary> {
         starting_transaction_index: je.starting_transaction_index,
     })
 }
+
+/// Check that entries describe the block they came with: starting transaction indexes are
+/// contiguous from 0, transaction counts add up to the block's, and the last entry hash is the
+/// blockhash. Returns the first inconsistency found. Empty entries are not checked.
+pub fn check_entries_consistency(
+    entries: &[EntrySummary],
+    transaction_count: usize,
+    blockhash: &str,
+) -> Result<()> {
+    let Some(last) = entries.last() else {
+        return Ok(());
+    };
+
+    let mut expected_index = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.starting_transaction_index != expected_index {
+            anyhow::bail!(
+                "entries[{i}] starts at transaction {}, expected {expected_index}",
+                entry.starting_transaction_index
+            );
+        }
+        expected_index += entry.num_transactions as usize;
+    }
+
+    if expected_index != transaction_count {
+        anyhow::bail!(
+            "Entries hold {expected_index} transactions, the block has {transaction_count}"
+        );
+    }
+
+    if last.hash.to_string() != blockhash {
+        anyhow::bail!(
+            "Last entry hash {} does not match blockhash {blockhash}",
+            last.hash
+        );
+    }
+    Ok(())
+}
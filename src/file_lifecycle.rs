@@ -0,0 +1,105 @@
+use {
+    crate::file_storage::{file_name, parent_dir, FileStorage},
+    anyhow::{Context, Result},
+    bytes::Bytes,
+    log::info,
+    serde_json::json,
+    std::time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const DEFAULT_DONE_DIR: &str = "done";
+pub const DEFAULT_FAILED_DIR: &str = "failed";
+pub const ERROR_REPORT_SUFFIX: &str = ".error.json";
+
+/// What to do with an input file after it has been ingested without errors.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SuccessAction {
+    /// Leave the file where it is.
+    #[default]
+    Keep,
+    /// Rename the file into the given directory.
+    MoveTo(String),
+    /// Delete the file.
+    Delete,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileLifecycleConfig {
+    pub on_success: SuccessAction,
+    /// Directory failed files are moved into, next to an `.error.json` report.
+    /// Failed files are left in place when unset.
+    pub failed_dir: Option<String>,
+}
+
+impl FileLifecycleConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.on_success != SuccessAction::Keep || self.failed_dir.is_some()
+    }
+}
+
+/// Apply the configured post-processing action to a file, based on its processing result.
+pub async fn finalize_file<S>(
+    storage: &S,
+    config: &FileLifecycleConfig,
+    file_path: &str,
+    result: &Result<()>,
+) -> Result<()>
+where
+    S: FileStorage + ?Sized,
+{
+    match result {
+        Ok(()) => match &config.on_success {
+            SuccessAction::Keep => Ok(()),
+            SuccessAction::MoveTo(dir) => {
+                let dst_path = target_path(file_path, dir);
+                storage.rename_file(file_path, &dst_path).await?;
+                info!("Moved processed file '{file_path}' to '{dst_path}'");
+                Ok(())
+            }
+            SuccessAction::Delete => {
+                storage.delete_file(file_path).await?;
+                info!("Deleted processed file '{file_path}'");
+                Ok(())
+            }
+        },
+        Err(err) => {
+            let Some(dir) = &config.failed_dir else {
+                return Ok(());
+            };
+            let dst_path = target_path(file_path, dir);
+            storage.rename_file(file_path, &dst_path).await?;
+            info!("Moved failed file '{file_path}' to '{dst_path}'");
+
+            let report_path = format!("{dst_path}{ERROR_REPORT_SUFFIX}");
+            let report = json!({
+                "file": file_path,
+                "moved_to": dst_path,
+                "error": format!("{err:#}"),
+                "failed_at": SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+            });
+            storage
+                .write_file(&report_path, Bytes::from(report.to_string()))
+                .await
+                .with_context(|| format!("Failed to write error report for '{file_path}'"))
+        }
+    }
+}
+
+/// Resolve the destination of `file_path` inside `dir`. Relative directories are resolved
+/// against the directory of the file itself, so `done` means a sibling `done/` directory.
+fn target_path(file_path: &str, dir: &str) -> String {
+    let dir = dir.trim_end_matches('/');
+    let name = file_name(file_path);
+    if dir.starts_with('/') || dir.contains("://") {
+        format!("{dir}/{name}")
+    } else {
+        match parent_dir(file_path) {
+            Some("/") => format!("/{dir}/{name}"),
+            Some(parent) => format!("{parent}/{dir}/{name}"),
+            None => format!("{dir}/{name}"),
+        }
+    }
+}
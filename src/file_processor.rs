@@ -1,29 +1,53 @@
 use {
     crate::{
+        block_envelope::BatchBlock,
         block_processor::{BlockProcessorTrait},
+        checksum::{ChecksumAlgorithm, ChecksumReader, ExpectedChecksum},
         decompressor::Decompressor,
-        file_storage::FileStorage,
+        file_lifecycle::{finalize_file, FileLifecycleConfig, ERROR_REPORT_SUFFIX},
+        file_registry::{unix_timestamp, FileRegistry, FileRegistryEntry, FileStatus},
+        file_storage::{is_not_found, FileStorage},
         format_parser::FormatParser,
-        message_decoder::DecodedPayload,
-        record_stream::{NdJsonRecordStream, RecordStream},
+        message_decoder::{DecodedPayload, FileRangeRef},
+        record_stream::{NdJsonRecordStream, RangeRecordStream, RecordStream},
     },
-    anyhow::{Context, Result},
-    log::{error, info},
+    anyhow::{anyhow, Context, Result},
+    futures::stream::{self, StreamExt},
+    log::{error, info, warn},
+    solana_sdk::clock::Slot,
     std::{sync::Arc, time::Instant},
+    tokio::io::AsyncReadExt,
 };
 
+/// Number of blocks of a batch uploaded at the same time.
+const BATCH_UPLOAD_CONCURRENCY: usize = 8;
+
 #[async_trait::async_trait]
 pub trait Processor {
     /// Process a single decoded payload.
     async fn process_decoded(&self, decoded: DecodedPayload) -> Result<()>;
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct FileProcessorConfig {
+    /// Post-processing applied to each file once it has been processed.
+    pub lifecycle: FileLifecycleConfig,
+    /// Process files even if the registry already marks them, or a copy of them, as done.
+    pub force_reprocess: bool,
+    /// Verify files against `<file>.sha256` or `<file>.md5` sidecars when present.
+    pub verify_checksum_sidecars: bool,
+    /// Fail files for which no expected checksum is known.
+    pub require_checksum: bool,
+}
+
 /// High-level processor that ties storage, decompression, parsing, and block uploading logic together.
 pub struct FileProcessor<S> {
     storage: S,
     parser: Arc<dyn FormatParser + Send + Sync>, // Updated to use trait object
     block_processor: Box<dyn BlockProcessorTrait + Send + Sync>,
     decompressor: Box<dyn Decompressor + Send + Sync>, // Boxed for dynamic dispatch
+    config: FileProcessorConfig,
+    registry: Option<Arc<dyn FileRegistry>>,
 }
 
 #[async_trait::async_trait]
@@ -34,6 +58,13 @@ where
     async fn process_decoded(&self, decoded: DecodedPayload) -> Result<()> {
         match decoded {
             DecodedPayload::FilePath(path) => self.process_file(&path).await,
+            DecodedPayload::FilePathWithChecksum(path, checksum) => {
+                self.process_file_with_checksum(&path, Some(checksum)).await
+            }
+            DecodedPayload::FileSplit(path, offset, length) => {
+                self.process_file_range(&path, offset, length).await
+            }
+            DecodedPayload::FileRange(range) => self.process_file_record(&range).await,
             DecodedPayload::Block(block_id, block) => {
                 self.block_processor.handle_block(block_id, block).await
             }
@@ -42,10 +73,34 @@ where
                     .handle_block_with_entries(block_id, block, entries)
                     .await
             }
+            DecodedPayload::VersionedBlock(block_id, block) => {
+                self.block_processor
+                    .handle_versioned_block_with_entries(block_id, block)
+                    .await
+            }
+            DecodedPayload::Blocks(blocks) => self.process_blocks(blocks).await,
         }
     }
 }
 
+/// A block of a multi-block batch that failed to decode or upload.
+#[derive(Debug)]
+pub struct BatchFailure {
+    pub index: usize,
+    pub slot: Option<Slot>,
+    /// Raw JSON of the block envelope, for dead-lettering the block on its own.
+    pub raw: String,
+    pub error: anyhow::Error,
+}
+
+/// Returned when some blocks of a batch failed. All other blocks were uploaded.
+#[derive(Debug, thiserror::Error)]
+#[error("{} of {total} blocks in batch failed", failures.len())]
+pub struct BatchError {
+    pub total: usize,
+    pub failures: Vec<BatchFailure>,
+}
+
 impl<S> FileProcessor<S>
 where
     S: FileStorage + Send + Sync,
@@ -55,15 +110,39 @@ where
         parser: Arc<dyn FormatParser + Send + Sync>, // Fixed number of arguments
         block_processor: Box<dyn BlockProcessorTrait + Send + Sync>,
         decompressor: Box<dyn Decompressor + Send + Sync>,
+    ) -> Self {
+        Self::new_with_config(
+            storage,
+            parser,
+            block_processor,
+            decompressor,
+            FileProcessorConfig::default(),
+        )
+    }
+
+    pub fn new_with_config(
+        storage: S,
+        parser: Arc<dyn FormatParser + Send + Sync>,
+        block_processor: Box<dyn BlockProcessorTrait + Send + Sync>,
+        decompressor: Box<dyn Decompressor + Send + Sync>,
+        config: FileProcessorConfig,
     ) -> Self {
         Self {
             storage,
             parser,
             block_processor,
             decompressor,
+            config,
+            registry: None,
         }
     }
 
+    /// Track per-file ingestion status in the given registry.
+    pub fn with_registry(mut self, registry: Arc<dyn FileRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
     /// Process all files in a directory.
     #[allow(unused)]
     pub async fn process_directory(&self, dir_path: &str) -> Result<()> {
@@ -73,31 +152,148 @@ where
             .await
             .with_context(|| format!("Failed to list directory '{dir_path}'"))?;
 
-        info!("Processing files in directory '{}':", dir_path);
-        for entry in entries {
-            if !entry.is_dir {
-                if let Err(e) = self.process_file(&entry.path).await {
-                    error!("Error processing file '{}': {}", entry.path, e);
+        let files: Vec<_> = entries
+            .into_iter()
+            .filter(|entry| !entry.is_dir && !is_sidecar_file(&entry.path))
+            .collect();
+
+        if let Some(registry) = &self.registry {
+            for file in &files {
+                if let Err(e) = self.mark_queued(registry.as_ref(), &file.path).await {
+                    error!("Failed to mark file '{}' as queued: {:#}", file.path, e);
                 }
             }
         }
 
+        info!("Processing files in directory '{}':", dir_path);
+        for file in files {
+            if let Err(e) = self.process_file(&file.path).await {
+                error!("Error processing file '{}': {}", file.path, e);
+            }
+        }
+
         Ok(())
     }
 
-    /// Process a single file:
-    ///  1. Open it from storage
-    ///  2. Decompress (if needed)
-    ///  3. Read lines from the record stream
-    ///  4. Parse each line into a block
-    ///  5. Pass each block to the BlockProcessor
+    /// Process a single file, verifying it against a checksum sidecar if configured.
     pub async fn process_file(&self, file_path: &str) -> Result<()> {
+        self.process_file_with_checksum(file_path, None).await
+    }
+
+    /// Process a single file:
+    ///  1. Skip it if the registry already marks it, or a file with its expected MD5 checksum,
+    ///     as done
+    ///  2. Open it from storage
+    ///  3. Decompress (if needed), checksumming the raw file contents as they stream
+    ///  4. Read lines from the record stream
+    ///  5. Parse each line into a block
+    ///  6. Pass each block to the BlockProcessor
+    ///  7. Fail it if the raw file contents do not match the expected checksum
+    ///  8. Record the outcome in the registry
+    ///  9. Move, delete or keep the file according to the lifecycle config
+    pub async fn process_file_with_checksum(
+        &self,
+        file_path: &str,
+        expected_checksum: Option<ExpectedChecksum>,
+    ) -> Result<()> {
+        let mut entry = FileRegistryEntry::new(file_path, FileStatus::Running);
+        entry.started_at = Some(unix_timestamp());
+
+        if let Some(registry) = &self.registry {
+            if !self.config.force_reprocess {
+                let done = registry
+                    .get(file_path)
+                    .await?
+                    .filter(|existing| existing.status == FileStatus::Done);
+                if let Some(existing) = done {
+                    info!(
+                        "Skipping file '{file_path}': already ingested (checksum={:?})",
+                        existing.checksum
+                    );
+                    return Ok(());
+                }
+            }
+            if let Err(e) = registry.put(&entry).await {
+                error!("Failed to mark file '{file_path}' as running: {e:#}");
+            }
+        }
+
+        let result = match self.resolve_checksum(file_path, expected_checksum).await {
+            Ok(expected_checksum) => {
+                self.ingest_file(file_path, expected_checksum.as_ref(), &mut entry)
+                    .await
+            }
+            Err(e) => {
+                entry.record_error(&e);
+                Err(e)
+            }
+        };
+
+        if let Some(registry) = &self.registry {
+            entry.status = if result.is_ok() {
+                FileStatus::Done
+            } else {
+                FileStatus::Failed
+            };
+            entry.finished_at = Some(unix_timestamp());
+            if let Err(e) = registry.put(&entry).await {
+                error!("Failed to record file '{file_path}' in registry: {e:#}");
+            }
+        }
+
+        if self.config.lifecycle.is_enabled() {
+            if let Err(e) =
+                finalize_file(&self.storage, &self.config.lifecycle, file_path, &result).await
+            {
+                error!("Failed to post-process file '{file_path}': {e:#}");
+            }
+        }
+
+        result
+    }
+
+    async fn ingest_file(
+        &self,
+        file_path: &str,
+        expected_checksum: Option<&ExpectedChecksum>,
+        entry: &mut FileRegistryEntry,
+    ) -> Result<()> {
         info!("Reading file: {file_path}");
         let start_time = Instant::now();
 
+        // A copy with the same contents can only be found before the file is read if its MD5
+        // checksum is known up front
+        let expected_md5 = expected_checksum
+            .filter(|expected| expected.algorithm == ChecksumAlgorithm::Md5)
+            .map(|expected| expected.hex.as_str());
+        if let Some(existing) = self.find_ingested_copy(file_path, expected_md5).await? {
+            info!(
+                "Skipping file '{file_path}': same contents as '{}', already ingested",
+                existing.path
+            );
+            entry.checksum = expected_md5.map(str::to_string);
+            entry.duplicate_of = Some(existing.path);
+            return Ok(());
+        }
+
         // Open a file
         let raw_file = self.storage.open_file(file_path).await?;
 
+        // Checksum the raw bytes as they stream, for verification and for the registry
+        let mut algorithms = vec![];
+        if let Some(expected) = expected_checksum {
+            algorithms.push(expected.algorithm);
+        }
+        if self.registry.is_some() {
+            algorithms.push(ChecksumAlgorithm::Md5);
+        }
+        let (raw_file, checksum) = if algorithms.is_empty() {
+            (raw_file, None)
+        } else {
+            let (reader, handle) = ChecksumReader::new(raw_file, &algorithms);
+            (Box::new(reader) as Box<_>, Some(handle))
+        };
+
         // Decompress
         let decompressed_reader = self.decompressor.decompress(raw_file);
 
@@ -105,34 +301,296 @@ where
         let mut record_stream = NdJsonRecordStream::new(decompressed_reader);
 
         // Record partial processing for dead-letter queue
+        let mut first_line_err = self
+            .process_records(file_path, &mut record_stream, entry)
+            .await;
+
+        // The blocks of a file that does not match its checksum, e.g. a truncated copy, are
+        // uploaded by now, but the file fails so that it is not marked as done
+        if let (Some(expected), Some(handle)) = (expected_checksum, &checksum) {
+            if let Err(e) = handle.verify(expected) {
+                let e = e.context(format!("File '{file_path}' failed checksum verification"));
+                error!("{e:#}");
+                entry.record_error(&e);
+                first_line_err = Some(e);
+            }
+        }
+
+        if first_line_err.is_none() {
+            entry.checksum = checksum.and_then(|handle| handle.hex_digest(ChecksumAlgorithm::Md5));
+        }
+
+        let duration = start_time.elapsed();
+        info!(
+            "Finished processing file '{file_path}'. Total time: {} ms",
+            duration.as_millis()
+        );
+        first_line_err.map_or(Ok(()), Err)
+    }
+
+    /// Process an uncompressed NDJSON file partially: only records that start within
+    /// `[offset, offset + length]` are processed, where a record straddling `offset` belongs
+    /// to the previous range. The registry, checksums and lifecycle actions apply to whole
+    /// files only and are not used here.
+    pub async fn process_file_range(&self, file_path: &str, offset: u64, length: u64) -> Result<()> {
+        if is_compressed_file(file_path) {
+            return Err(anyhow!(
+                "Byte ranges are not supported for compressed file '{file_path}'"
+            ));
+        }
+
+        let end = offset.saturating_add(length);
+        info!("Reading file: {file_path} [bytes {offset}..{end}]");
+        let start_time = Instant::now();
+
+        let reader = self
+            .storage
+            .open_file_range(file_path, offset)
+            .await?;
+        let mut record_stream = RangeRecordStream::new(reader, offset, end);
+
+        let mut entry = FileRegistryEntry::new(file_path, FileStatus::Running);
+        let first_line_err = self
+            .process_records(file_path, &mut record_stream, &mut entry)
+            .await;
+
+        info!(
+            "Finished processing file '{file_path}' [bytes {offset}..{end}]: {} blocks, slots {:?}..{:?}. Total time: {} ms",
+            entry.blocks_uploaded,
+            entry.first_slot,
+            entry.last_slot,
+            start_time.elapsed().as_millis()
+        );
+        first_line_err.map_or(Ok(()), Err)
+    }
+
+    /// Fetch a single block record from a file and process it like an inline block.
+    pub async fn process_file_record(&self, range: &FileRangeRef) -> Result<()> {
+        let FileRangeRef {
+            path,
+            offset,
+            length,
+            compression,
+            slot,
+        } = range;
+
+        let data = self.storage.read_range(path, *offset, *length).await?;
+        let mut reader = compression
+            .decompressor()
+            .decompress(Box::new(std::io::Cursor::new(data)));
+
+        let mut record = String::new();
+        reader
+            .read_to_string(&mut record)
+            .await
+            .with_context(|| format!("Failed to read record {offset}+{length} from '{path}'"))?;
+
+        let (block_id, block, entries) = self
+            .parser
+            .parse_record(&mut record)
+            .with_context(|| format!("Failed to parse record {offset}+{length} from '{path}'"))?
+            .ok_or_else(|| anyhow!("No block found in record {offset}+{length} of '{path}'"))?;
+
+        if let Some(slot) = slot {
+            if *slot != block_id {
+                return Err(anyhow!(
+                    "Record {offset}+{length} of '{path}' holds slot {block_id}, expected {slot}"
+                ));
+            }
+        }
+
+        self.block_processor
+            .handle_block_with_entries(block_id, block, entries)
+            .await
+    }
+
+    /// Read + parse all records of a stream, collecting stats in `entry`.
+    /// Returns the first error encountered, if any.
+    async fn process_records(
+        &self,
+        file_path: &str,
+        record_stream: &mut dyn RecordStream,
+        entry: &mut FileRegistryEntry,
+    ) -> Option<anyhow::Error> {
         let mut first_line_err: Option<anyhow::Error> = None;
 
-        // Read + parse lines
         while let Some(line_result) = record_stream.next_record().await {
             match line_result {
-                Ok(line) => {
-                    if let Err(e) = self.process_line(&line).await {
-                        first_line_err.get_or_insert(e);
+                Ok(mut line) => {
+                    entry.lines_read += 1;
+                    match self.process_line(&mut line).await {
+                        Ok(Some(slot)) => {
+                            entry.blocks_uploaded += 1;
+                            entry.record_slot(slot);
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            entry.record_error(&e);
+                            first_line_err.get_or_insert(e);
+                        }
                     }
                 }
                 Err(e) => {
                     error!("Error reading line from file '{file_path}': {e} [Skipping file]");
+                    entry.record_error(&e);
                     first_line_err.get_or_insert(e);
                     break;
                 }
             }
         }
 
-        let duration = start_time.elapsed();
+        first_line_err
+    }
+
+    /// Determine the checksum a file is expected to match: the one passed in by the caller,
+    /// otherwise the one found in a sidecar file if sidecar verification is enabled.
+    async fn resolve_checksum(
+        &self,
+        file_path: &str,
+        expected_checksum: Option<ExpectedChecksum>,
+    ) -> Result<Option<ExpectedChecksum>> {
+        let expected_checksum = match expected_checksum {
+            Some(checksum) => Some(checksum),
+            None if self.config.verify_checksum_sidecars => {
+                self.read_checksum_sidecar(file_path).await?
+            }
+            None => None,
+        };
+
+        if expected_checksum.is_none() && self.config.require_checksum {
+            return Err(anyhow!("No checksum available for file '{file_path}'"));
+        }
+        Ok(expected_checksum)
+    }
+
+    /// Look up a file with the given MD5 checksum that the registry marks as done, under another
+    /// path.
+    async fn find_ingested_copy(
+        &self,
+        file_path: &str,
+        md5: Option<&str>,
+    ) -> Result<Option<FileRegistryEntry>> {
+        let (Some(registry), Some(md5)) = (&self.registry, md5) else {
+            return Ok(None);
+        };
+        if self.config.force_reprocess {
+            return Ok(None);
+        }
+        Ok(registry
+            .get_by_checksum(md5)
+            .await?
+            .filter(|existing| existing.status == FileStatus::Done && existing.path != file_path))
+    }
+
+    async fn read_checksum_sidecar(&self, file_path: &str) -> Result<Option<ExpectedChecksum>> {
+        for algorithm in ChecksumAlgorithm::ALL {
+            let sidecar_path = format!("{file_path}{}", algorithm.sidecar_suffix());
+            let mut sidecar = match self.storage.open_file(&sidecar_path).await {
+                Ok(sidecar) => sidecar,
+                Err(e) if is_not_found(&e) => continue,
+                Err(e) => {
+                    return Err(e.context(format!("Failed to open checksum file '{sidecar_path}'")))
+                }
+            };
+
+            let mut contents = String::new();
+            sidecar
+                .read_to_string(&mut contents)
+                .await
+                .with_context(|| format!("Failed to read checksum file '{sidecar_path}'"))?;
+            let checksum = ExpectedChecksum::from_sidecar(algorithm, &contents)
+                .with_context(|| format!("Invalid checksum file '{sidecar_path}'"))?;
+            return Ok(Some(checksum));
+        }
+
+        warn!("No checksum sidecar found for file '{file_path}'");
+        Ok(None)
+    }
+
+    async fn mark_queued(&self, registry: &dyn FileRegistry, file_path: &str) -> Result<()> {
+        let existing = registry.get(file_path).await?;
+        if existing.is_some_and(|e| e.status == FileStatus::Done) {
+            return Ok(());
+        }
+        registry
+            .put(&FileRegistryEntry::new(file_path, FileStatus::Queued))
+            .await
+    }
+
+    /// Upload the blocks of a batch, up to `BATCH_UPLOAD_CONCURRENCY` at a time, or one at a
+    /// time in slot order if blocks are checked against their parent. A failed block does not
+    /// stop the others, and all failures are returned together as a `BatchError`.
+    pub async fn process_blocks(&self, mut blocks: Vec<BatchBlock>) -> Result<()> {
+        let total = blocks.len();
+        let concurrency = if self.block_processor.checks_parent() {
+            blocks.sort_by_key(|block| {
+                block
+                    .envelope
+                    .as_ref()
+                    .ok()
+                    .map(|envelope| envelope.block_id)
+            });
+            1
+        } else {
+            BATCH_UPLOAD_CONCURRENCY
+        };
+        let failures: Vec<BatchFailure> = stream::iter(blocks)
+            .map(|block| self.process_batch_block(block))
+            .buffered(concurrency)
+            .filter_map(|failure| async move { failure })
+            .collect()
+            .await;
+
         info!(
-            "Finished processing file '{file_path}'. Total time: {} ms",
-            duration.as_millis()
+            "Processed batch of {total} blocks, {} failed",
+            failures.len()
         );
-        first_line_err.map_or(Ok(()), Err)
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(BatchError { total, failures }.into())
+        }
+    }
+
+    /// Upload one block of a batch, returning the failure if it could not be uploaded.
+    async fn process_batch_block(
+        &self,
+        BatchBlock {
+            index,
+            raw,
+            envelope,
+        }: BatchBlock,
+    ) -> Option<BatchFailure> {
+        let envelope = match envelope {
+            Ok(envelope) => envelope,
+            Err(error) => {
+                error!("Failed to decode block {index} of batch: {error}");
+                return Some(BatchFailure {
+                    index,
+                    slot: None,
+                    raw,
+                    error,
+                });
+            }
+        };
+        let slot = envelope.block_id;
+        let error = self
+            .block_processor
+            .handle_block_with_entries(slot, envelope.block, envelope.entries)
+            .await
+            .err()?;
+        error!("Failed to handle block {index} of batch - slot={slot}: {error}");
+        Some(BatchFailure {
+            index,
+            slot: Some(slot),
+            raw,
+            error,
+        })
     }
 
-    /// Process a single line from the record stream.
-    pub async fn process_line(&self, line: &str) -> Result<()> {
+    /// Process a single line from the record stream, parsing it in place.
+    /// Returns the slot of the uploaded block, or `None` if the line held no block.
+    pub async fn process_line(&self, line: &mut String) -> Result<Option<Slot>> {
         match self.parser.parse_record(line) {
             Ok(Some((block_id, block, entries))) => {
                 if let Err(err) = self
@@ -143,15 +601,29 @@ where
                     error!("Error handling block: {err}");
                     return Err(err.context(format!("Failed to handle block ID {}", block_id)));
                 }
+                Ok(Some(block_id))
             }
             Ok(None) => {
                 // empty line or no blockID
+                Ok(None)
             }
             Err(e) => {
                 error!("Failed to parse record: {e}");
-                return Err(e);
+                Err(e)
             }
         }
-        Ok(())
     }
 }
+
+/// Checksum sidecars and error reports live next to input files and are not inputs themselves.
+fn is_sidecar_file(path: &str) -> bool {
+    path.ends_with(ERROR_REPORT_SUFFIX)
+        || ChecksumAlgorithm::ALL
+            .iter()
+            .any(|algorithm| path.ends_with(algorithm.sidecar_suffix()))
+}
+
+/// Compressed files can only be read from the start.
+pub fn is_compressed_file(path: &str) -> bool {
+    path.ends_with(".gz")
+}
@@ -0,0 +1,144 @@
+use {
+    crate::hbase::HBaseConnection,
+    anyhow::{Context, Result},
+    async_trait::async_trait,
+    serde::{Deserialize, Serialize},
+    solana_sdk::clock::Slot,
+    std::time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const FILE_REGISTRY_TABLE_NAME: &str = "ingestor_file_registry";
+pub const INGESTOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Maximum number of error messages kept per registry entry.
+const MAX_RECORDED_ERRORS: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// Ingestion state of a single input file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileRegistryEntry {
+    pub path: String,
+    /// MD5 of the raw (still compressed) file contents, known once the file was fully read.
+    pub checksum: Option<String>,
+    /// Path of the file with the same contents that was ingested instead of this one.
+    pub duplicate_of: Option<String>,
+    pub status: FileStatus,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub lines_read: u64,
+    pub blocks_uploaded: u64,
+    pub errors: Vec<String>,
+    pub first_slot: Option<Slot>,
+    pub last_slot: Option<Slot>,
+    pub ingestor_version: String,
+}
+
+impl FileRegistryEntry {
+    pub fn new(path: &str, status: FileStatus) -> Self {
+        Self {
+            path: path.to_string(),
+            checksum: None,
+            duplicate_of: None,
+            status,
+            started_at: None,
+            finished_at: None,
+            lines_read: 0,
+            blocks_uploaded: 0,
+            errors: vec![],
+            first_slot: None,
+            last_slot: None,
+            ingestor_version: INGESTOR_VERSION.to_string(),
+        }
+    }
+
+    pub fn record_slot(&mut self, slot: Slot) {
+        self.first_slot = Some(self.first_slot.map_or(slot, |s| s.min(slot)));
+        self.last_slot = Some(self.last_slot.map_or(slot, |s| s.max(slot)));
+    }
+
+    pub fn record_error(&mut self, err: &anyhow::Error) {
+        if self.errors.len() < MAX_RECORDED_ERRORS {
+            self.errors.push(format!("{err:#}"));
+        }
+    }
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[async_trait]
+pub trait FileRegistry: Send + Sync {
+    /// Look up the latest entry for a file path.
+    async fn get(&self, path: &str) -> Result<Option<FileRegistryEntry>>;
+
+    /// Store an entry under its path and, if known, under its checksum unless it is a duplicate.
+    async fn put(&self, entry: &FileRegistryEntry) -> Result<()>;
+
+    /// Look up an entry by content checksum, regardless of where the file was read from.
+    async fn get_by_checksum(&self, checksum: &str) -> Result<Option<FileRegistryEntry>>;
+}
+
+fn path_key(path: &str) -> String {
+    format!("path:{path}")
+}
+
+fn checksum_key(checksum: &str) -> String {
+    format!("md5:{checksum}")
+}
+
+/// File registry stored in an HBase table, one bincode row per path and per checksum.
+pub struct HBaseFileRegistry {
+    connection: HBaseConnection,
+    table_name: String,
+}
+
+impl HBaseFileRegistry {
+    pub fn new(connection: HBaseConnection, table_name: &str) -> Self {
+        Self {
+            connection,
+            table_name: table_name.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl FileRegistry for HBaseFileRegistry {
+    async fn get(&self, path: &str) -> Result<Option<FileRegistryEntry>> {
+        self.connection
+            .get_bincode_cell_with_retry(&self.table_name, &path_key(path))
+            .await
+            .with_context(|| format!("Failed to read file registry entry for '{path}'"))
+    }
+
+    async fn put(&self, entry: &FileRegistryEntry) -> Result<()> {
+        let mut cells = vec![(path_key(&entry.path), entry)];
+        // The checksum keeps pointing at the file that was actually ingested
+        if let (Some(checksum), None) = (&entry.checksum, &entry.duplicate_of) {
+            cells.push((checksum_key(checksum), entry));
+        }
+
+        self.connection
+            .put_bincode_cells_with_retry(&self.table_name, &cells, false, true)
+            .await
+            .with_context(|| format!("Failed to write file registry entry for '{}'", entry.path))?;
+        Ok(())
+    }
+
+    async fn get_by_checksum(&self, checksum: &str) -> Result<Option<FileRegistryEntry>> {
+        self.connection
+            .get_bincode_cell_with_retry(&self.table_name, &checksum_key(checksum))
+            .await
+            .with_context(|| format!("Failed to read file registry entry for checksum {checksum}"))
+    }
+}
@@ -0,0 +1,95 @@
+use {
+    crate::{
+        file_processor::{is_compressed_file, Processor},
+        file_storage::FileStorage,
+        message_decoder::DecodedPayload,
+        queue_producer::QueueProducer,
+    },
+    anyhow::{Context, Result},
+    bytes::BytesMut,
+    log::info,
+    serde_json::json,
+    std::sync::Arc,
+};
+
+/// Coordinator that splits uncompressed files into byte-range messages, so several ingestor
+/// replicas can share one file. All other payloads are passed to the inner processor,
+/// including the byte-range messages themselves.
+pub struct FileSplitter<S, P> {
+    storage: S,
+    producer: P,
+    inner: Arc<dyn Processor + Send + Sync>,
+    num_splits: u64,
+}
+
+impl<S, P> FileSplitter<S, P>
+where
+    S: FileStorage + Send + Sync,
+    P: QueueProducer + Send + Sync,
+{
+    pub fn new(
+        storage: S,
+        producer: P,
+        inner: Arc<dyn Processor + Send + Sync>,
+        num_splits: u64,
+    ) -> Self {
+        Self {
+            storage,
+            producer,
+            inner,
+            num_splits: num_splits.max(1),
+        }
+    }
+
+    /// Publish one `{"hdfs_path", "offset", "length"}` message per byte range of the file.
+    pub async fn split_file(&self, file_path: &str) -> Result<()> {
+        let file_size = self.storage.file_size(file_path).await?;
+        let ranges = split_ranges(file_size, self.num_splits);
+
+        for (offset, length) in &ranges {
+            let payload = json!({
+                "hdfs_path": file_path,
+                "offset": offset,
+                "length": length,
+            })
+            .to_string();
+            self.producer
+                .produce_message(BytesMut::from(payload.as_str()), None)
+                .await
+                .with_context(|| {
+                    format!("Failed to publish range {offset}+{length} of file '{file_path}'")
+                })?;
+        }
+
+        info!(
+            "Split file '{file_path}' ({file_size} bytes) into {} ranges",
+            ranges.len()
+        );
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<S, P> Processor for FileSplitter<S, P>
+where
+    S: FileStorage + Send + Sync,
+    P: QueueProducer + Send + Sync,
+{
+    async fn process_decoded(&self, decoded: DecodedPayload) -> Result<()> {
+        match decoded {
+            DecodedPayload::FilePath(path) if !is_compressed_file(&path) => {
+                self.split_file(&path).await
+            }
+            other => self.inner.process_decoded(other).await,
+        }
+    }
+}
+
+/// Split `[0, size)` into at most `num_splits` non-empty (offset, length) ranges.
+pub fn split_ranges(size: u64, num_splits: u64) -> Vec<(u64, u64)> {
+    let split_size = size.div_ceil(num_splits.max(1)).max(1);
+    (0..size)
+        .step_by(split_size as usize)
+        .map(|offset| (offset, split_size.min(size - offset)))
+        .collect()
+}
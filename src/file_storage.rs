@@ -2,6 +2,7 @@ use {
     anyhow::{Context, Result},
     bytes::Bytes,
     futures::{Stream, TryStreamExt},
+    hdfs_native::WriteOptions,
     std::{pin::Pin, sync::Arc},
     tokio::io::AsyncRead,
     tokio_util::io::StreamReader,
@@ -11,6 +12,20 @@ use {
 pub trait FileStorage: Send + Sync {
     async fn list_directory(&self, dir_path: &str) -> Result<Vec<FileMetadata>>;
     async fn open_file(&self, file_path: &str) -> Result<Box<dyn AsyncRead + Unpin + Send>>;
+    /// Open a file for reading from byte `offset` to the end of the file.
+    async fn open_file_range(
+        &self,
+        file_path: &str,
+        offset: u64,
+    ) -> Result<Box<dyn AsyncRead + Unpin + Send>>;
+    async fn file_size(&self, file_path: &str) -> Result<u64>;
+    /// Read exactly `length` bytes starting at byte `offset`.
+    async fn read_range(&self, file_path: &str, offset: u64, length: u64) -> Result<Bytes>;
+    /// Move a file, creating the parent directory of `dst_path` if needed.
+    async fn rename_file(&self, src_path: &str, dst_path: &str) -> Result<()>;
+    async fn delete_file(&self, file_path: &str) -> Result<()>;
+    /// Create (or overwrite) a file with the given contents.
+    async fn write_file(&self, file_path: &str, data: Bytes) -> Result<()>;
 }
 
 #[derive(Debug, Clone)]
@@ -19,6 +34,7 @@ pub struct FileMetadata {
     pub is_dir: bool,
 }
 
+#[derive(Clone)]
 pub struct HdfsStorage {
     client: Arc<hdfs_native::Client>,
 }
@@ -57,13 +73,140 @@ impl FileStorage for HdfsStorage {
             .await
             .with_context(|| format!("Failed to open file '{file_path}'"))?;
 
-        let stream = file_reader_stream(file);
-        let async_reader = StreamReader::new(
-            stream.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string())),
-        );
+        Ok(file_async_reader(file))
+    }
+
+    async fn open_file_range(
+        &self,
+        file_path: &str,
+        offset: u64,
+    ) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
+        let mut file = self
+            .client
+            .read(file_path)
+            .await
+            .with_context(|| format!("Failed to open file '{file_path}'"))?;
+
+        let file_length = file.file_length() as u64;
+        if offset > file_length {
+            anyhow::bail!(
+                "Offset {offset} is beyond the end of file '{file_path}' ({file_length} bytes)"
+            );
+        }
+        file.seek(offset as usize);
+
+        Ok(file_async_reader(file))
+    }
+
+    async fn read_range(&self, file_path: &str, offset: u64, length: u64) -> Result<Bytes> {
+        let file = self
+            .client
+            .read(file_path)
+            .await
+            .with_context(|| format!("Failed to open file '{file_path}'"))?;
 
-        Ok(Box::new(async_reader))
+        let file_length = file.file_length() as u64;
+        if offset.saturating_add(length) > file_length {
+            anyhow::bail!(
+                "Range {offset}+{length} is beyond the end of file '{file_path}' ({file_length} bytes)"
+            );
+        }
+
+        file.read_range(offset as usize, length as usize)
+            .await
+            .with_context(|| format!("Failed to read range {offset}+{length} of '{file_path}'"))
     }
+
+    async fn file_size(&self, file_path: &str) -> Result<u64> {
+        let status = self
+            .client
+            .get_file_info(file_path)
+            .await
+            .with_context(|| format!("Failed to get file info for '{file_path}'"))?;
+        Ok(status.length as u64)
+    }
+
+    async fn rename_file(&self, src_path: &str, dst_path: &str) -> Result<()> {
+        if let Some(parent) = parent_dir(dst_path) {
+            self.client
+                .mkdirs(parent, 0o755, true)
+                .await
+                .with_context(|| format!("Failed to create directory '{parent}'"))?;
+        }
+
+        self.client
+            .rename(src_path, dst_path, false)
+            .await
+            .with_context(|| format!("Failed to rename '{src_path}' to '{dst_path}'"))
+    }
+
+    async fn delete_file(&self, file_path: &str) -> Result<()> {
+        let deleted = self
+            .client
+            .delete(file_path, false)
+            .await
+            .with_context(|| format!("Failed to delete file '{file_path}'"))?;
+
+        if !deleted {
+            anyhow::bail!("File '{file_path}' was not deleted");
+        }
+        Ok(())
+    }
+
+    async fn write_file(&self, file_path: &str, data: Bytes) -> Result<()> {
+        let options = WriteOptions::default().overwrite(true).create_parent(true);
+        let mut writer = self
+            .client
+            .create(file_path, options)
+            .await
+            .with_context(|| format!("Failed to create file '{file_path}'"))?;
+
+        writer
+            .write(data)
+            .await
+            .with_context(|| format!("Failed to write file '{file_path}'"))?;
+        writer
+            .close()
+            .await
+            .with_context(|| format!("Failed to close file '{file_path}'"))?;
+
+        Ok(())
+    }
+}
+
+/// Whether a storage error means that the file does not exist.
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<hdfs_native::HdfsError>(),
+            Some(hdfs_native::HdfsError::FileNotFound(_))
+        ) || cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+    })
+}
+
+/// Returns the directory part of a path, e.g. `/a/b` for `/a/b/c.gz`.
+pub fn parent_dir(path: &str) -> Option<&str> {
+    match path.trim_end_matches('/').rfind('/') {
+        Some(0) => Some("/"),
+        Some(idx) => Some(&path[..idx]),
+        None => None,
+    }
+}
+
+/// Returns the last component of a path, e.g. `c.gz` for `/a/b/c.gz`.
+pub fn file_name(path: &str) -> &str {
+    let trimmed = path.trim_end_matches('/');
+    trimmed.rsplit('/').next().unwrap_or(trimmed)
+}
+
+/// Wrap an HDFS file reader, from its current position, into an `AsyncRead`.
+fn file_async_reader(reader: hdfs_native::file::FileReader) -> Box<dyn AsyncRead + Unpin + Send> {
+    let stream = file_reader_stream(reader);
+    Box::new(StreamReader::new(
+        stream.map_err(|e| std::io::Error::other(e.to_string())),
+    ))
 }
 
 /// A helper function for HDFS to create an asynchronous stream of `Bytes`.
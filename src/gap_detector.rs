@@ -0,0 +1,332 @@
+use {
+    crate::{ledger_storage::LedgerStorage, queue_producer::QueueProducer},
+    anyhow::{anyhow, Context, Result},
+    bytes::BytesMut,
+    log::info,
+    serde::Serialize,
+    solana_sdk::clock::Slot,
+    std::collections::{BTreeMap, HashSet},
+};
+
+/// Topic the fetchers backfill missing slots from.
+pub const DEFAULT_MISSING_SLOTS_TOPIC: &str = "sol.missing.blocks";
+
+/// Number of slots read from indexing progress per scan.
+const SCAN_CHUNK_SLOTS: u64 = 100_000;
+
+/// How far past the end of the range to look for a stored block whose parent link covers the
+/// last slots of the range.
+const LINK_LOOKAHEAD_SLOTS: u64 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct GapDetectorConfig {
+    /// First slot of the range.
+    pub start_slot: Slot,
+    /// Last slot of the range, included.
+    pub end_slot: Slot,
+    /// Tell skipped slots from missing ones using the parent slots of stored blocks.
+    pub use_parent_links: bool,
+}
+
+/// Why a slot without indexing progress is reported as missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingReason {
+    /// The slot has no indexing progress; parent links were not used.
+    NotStored,
+    /// A stored block names the slot as its parent, so the slot has a block.
+    ParentOfStoredBlock,
+    /// No stored block links to or over the slot, so it may also have been skipped.
+    Unlinked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MissingSlot {
+    pub slot: Slot,
+    pub reason: MissingReason,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GapSummary {
+    pub start_slot: Slot,
+    pub end_slot: Slot,
+    /// Slots with indexing progress.
+    pub stored: u64,
+    /// Stored slots whose indexing progress does not decode. They count as stored but give no
+    /// parent link.
+    pub undecodable: u64,
+    /// Slots without indexing progress that stored blocks skip over.
+    pub skipped: u64,
+    /// Slots reported as missing.
+    pub missing: u64,
+    /// Missing slots published to the missing slots topic.
+    pub published: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GapReport {
+    pub missing: Vec<MissingSlot>,
+    pub summary: GapSummary,
+}
+
+/// Finds the slots of a range that have no indexing progress.
+pub struct GapDetector {
+    storage: LedgerStorage,
+    config: GapDetectorConfig,
+}
+
+impl GapDetector {
+    pub fn new(storage: LedgerStorage, config: GapDetectorConfig) -> Self {
+        Self { storage, config }
+    }
+
+    pub async fn detect(&self) -> Result<GapReport> {
+        let GapDetectorConfig {
+            start_slot,
+            end_slot,
+            use_parent_links,
+        } = self.config;
+        if start_slot > end_slot {
+            return Err(anyhow!(
+                "Start slot {start_slot} is after end slot {end_slot}"
+            ));
+        }
+
+        let stored = self.stored_parent_slots(start_slot, end_slot).await?;
+        let next_stored = if use_parent_links {
+            self.next_stored_parent_slot(end_slot).await?
+        } else {
+            None
+        };
+        Ok(find_gaps(
+            start_slot,
+            end_slot,
+            &stored,
+            next_stored,
+            use_parent_links,
+        ))
+    }
+
+    /// Parent slots of the stored slots of a range, keyed by slot. The parent slot is `None` for
+    /// slots whose indexing progress does not decode.
+    async fn stored_parent_slots(
+        &self,
+        start_slot: Slot,
+        end_slot: Slot,
+    ) -> Result<BTreeMap<Slot, Option<Slot>>> {
+        let mut stored = BTreeMap::new();
+        let mut chunk_start = start_slot;
+        loop {
+            let chunk_end = chunk_start
+                .saturating_add(SCAN_CHUNK_SLOTS - 1)
+                .min(end_slot);
+            let progress = self
+                .storage
+                .get_indexing_progress_range(chunk_start, chunk_end)
+                .await
+                .with_context(|| {
+                    format!("Failed to read indexing progress for slots {chunk_start}-{chunk_end}")
+                })?;
+            stored.extend(
+                progress
+                    .into_iter()
+                    .map(|(slot, row)| (slot, row.map(|row| row.parent_slot))),
+            );
+            info!(
+                "Scanned indexing progress up to slot {chunk_end}, {} slots stored",
+                stored.len()
+            );
+            if chunk_end == end_slot {
+                return Ok(stored);
+            }
+            chunk_start = chunk_end + 1;
+        }
+    }
+
+    /// Slot and parent slot of the first stored slot after the range, if one is close enough.
+    async fn next_stored_parent_slot(&self, end_slot: Slot) -> Result<Option<(Slot, Slot)>> {
+        let Some(start_slot) = end_slot.checked_add(1) else {
+            return Ok(None);
+        };
+        let progress = self
+            .storage
+            .get_indexing_progress_range(
+                start_slot,
+                start_slot.saturating_add(LINK_LOOKAHEAD_SLOTS - 1),
+            )
+            .await
+            .context("Failed to read indexing progress after the end slot")?;
+        Ok(progress
+            .into_iter()
+            .find_map(|(slot, row)| row.map(|row| (slot, row.parent_slot))))
+    }
+}
+
+/// Classify the slots of a range without indexing progress. `stored` maps the stored slots of
+/// the range to their parent slots, if known, and `next_stored` is the first stored slot after
+/// the range with its parent slot.
+///
+/// Without parent links every slot that is not stored is missing. With parent links a slot that
+/// is the parent of a stored block is missing, a slot between the next stored block and its
+/// parent is skipped, and any other slot is reported as unlinked.
+pub fn find_gaps(
+    start_slot: Slot,
+    end_slot: Slot,
+    stored: &BTreeMap<Slot, Option<Slot>>,
+    next_stored: Option<(Slot, Slot)>,
+    use_parent_links: bool,
+) -> GapReport {
+    let parents: HashSet<Slot> = stored
+        .values()
+        .flatten()
+        .copied()
+        .chain(next_stored.map(|(_, parent_slot)| parent_slot))
+        .collect();
+
+    let mut summary = GapSummary {
+        start_slot,
+        end_slot,
+        stored: stored.len() as u64,
+        undecodable: stored.values().filter(|parent| parent.is_none()).count() as u64,
+        ..GapSummary::default()
+    };
+    let mut missing = vec![];
+    let mut next = stored
+        .iter()
+        .map(|(slot, parent)| (*slot, *parent))
+        .peekable();
+    for slot in start_slot..=end_slot {
+        while next
+            .next_if(|(stored_slot, _)| *stored_slot < slot)
+            .is_some()
+        {}
+        let next_parent_slot = match next.peek() {
+            Some((stored_slot, _)) if *stored_slot == slot => continue,
+            Some((_, parent_slot)) => *parent_slot,
+            None => next_stored.map(|(_, parent_slot)| parent_slot),
+        };
+
+        let reason = if !use_parent_links {
+            MissingReason::NotStored
+        } else if parents.contains(&slot) {
+            MissingReason::ParentOfStoredBlock
+        } else if next_parent_slot.is_some_and(|parent_slot| parent_slot < slot) {
+            summary.skipped += 1;
+            continue;
+        } else {
+            MissingReason::Unlinked
+        };
+        missing.push(MissingSlot { slot, reason });
+    }
+    summary.missing = missing.len() as u64;
+
+    GapReport { missing, summary }
+}
+
+/// Publish missing slots as JSON messages, returning how many were published.
+pub async fn publish_missing_slots(
+    producer: &(dyn QueueProducer + Send + Sync),
+    missing: &[MissingSlot],
+) -> Result<u64> {
+    let mut published = 0;
+    for missing_slot in missing {
+        let payload = serde_json::to_vec(missing_slot)?;
+        producer
+            .produce_message(BytesMut::from(payload.as_slice()), None)
+            .await
+            .with_context(|| format!("Failed to publish missing slot {}", missing_slot.slot))?;
+        published += 1;
+    }
+    Ok(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(slots: &[(Slot, Option<Slot>)]) -> BTreeMap<Slot, Option<Slot>> {
+        slots.iter().copied().collect()
+    }
+
+    fn missing(report: &GapReport) -> Vec<(Slot, MissingReason)> {
+        report
+            .missing
+            .iter()
+            .map(|missing_slot| (missing_slot.slot, missing_slot.reason))
+            .collect()
+    }
+
+    #[test]
+    fn test_without_parent_links_every_slot_not_stored_is_missing() {
+        let stored = stored(&[(11, Some(10)), (13, Some(11))]);
+        let report = find_gaps(10, 15, &stored, Some((20, 13)), false);
+        assert_eq!(
+            missing(&report),
+            vec![
+                (10, MissingReason::NotStored),
+                (12, MissingReason::NotStored),
+                (14, MissingReason::NotStored),
+                (15, MissingReason::NotStored),
+            ]
+        );
+        assert_eq!(report.summary.stored, 2);
+        assert_eq!(report.summary.skipped, 0);
+        assert_eq!(report.summary.missing, 4);
+    }
+
+    #[test]
+    fn test_parent_of_stored_block_is_missing() {
+        let stored = stored(&[(13, Some(11))]);
+        let report = find_gaps(10, 13, &stored, None, true);
+        assert_eq!(
+            missing(&report),
+            vec![
+                (10, MissingReason::Unlinked),
+                (11, MissingReason::ParentOfStoredBlock),
+            ]
+        );
+        assert_eq!(report.summary.skipped, 1);
+    }
+
+    #[test]
+    fn test_slots_before_the_next_stored_block_are_skipped() {
+        let stored = stored(&[(10, Some(9)), (14, Some(10))]);
+        let report = find_gaps(10, 14, &stored, None, true);
+        assert_eq!(missing(&report), vec![]);
+        assert_eq!(report.summary.stored, 2);
+        assert_eq!(report.summary.skipped, 3);
+        assert_eq!(report.summary.missing, 0);
+    }
+
+    #[test]
+    fn test_undecodable_stored_row_gives_no_parent_link() {
+        let stored = stored(&[(10, Some(9)), (12, None), (14, Some(12))]);
+        let report = find_gaps(10, 14, &stored, None, true);
+        assert_eq!(missing(&report), vec![(11, MissingReason::Unlinked)]);
+        assert_eq!(report.summary.stored, 3);
+        assert_eq!(report.summary.undecodable, 1);
+        assert_eq!(report.summary.skipped, 1);
+    }
+
+    #[test]
+    fn test_next_stored_block_past_the_range_links_its_last_slots() {
+        let stored = stored(&[(10, Some(9))]);
+        let report = find_gaps(10, 13, &stored, Some((16, 11)), true);
+        assert_eq!(
+            missing(&report),
+            vec![(11, MissingReason::ParentOfStoredBlock)]
+        );
+        assert_eq!(report.summary.skipped, 2);
+
+        // Without a stored block after the range its last slots are unlinked
+        let report = find_gaps(10, 13, &stored, None, true);
+        assert_eq!(
+            missing(&report),
+            vec![
+                (11, MissingReason::Unlinked),
+                (12, MissingReason::Unlinked),
+                (13, MissingReason::Unlinked),
+            ]
+        );
+    }
+}
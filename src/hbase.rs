@@ -1,6 +1,6 @@
 use {
     backoff::{future::retry_notify, ExponentialBackoff},
-    hbase_thrift::hbase::{BatchMutation, HbaseSyncClient, THbaseSyncClient},
+    hbase_thrift::hbase::{BatchMutation, HbaseSyncClient, ScannerID, THbaseSyncClient},
     hbase_thrift::MutationBuilder,
     log::*,
     // solana_block_decoder::{
@@ -10,7 +10,8 @@ use {
     //         CompressionMethod,
     //     },
     // },
-    solana_storage_utils::compression::{compress, compress_best, CompressionMethod},
+    solana_storage_utils::compression::{compress, compress_best, decompress, CompressionMethod},
+    std::collections::BTreeMap,
     thiserror::Error,
     thrift::{
         protocol::{TBinaryInputProtocol, TBinaryOutputProtocol},
@@ -30,6 +31,9 @@ pub enum Error {
 
     #[error("Thrift")]
     Thrift(thrift::Error),
+
+    #[error("Bincode: {0}")]
+    Bincode(bincode::Error),
 }
 
 impl std::convert::From<std::io::Error> for Error {
@@ -44,12 +48,19 @@ impl std::convert::From<thrift::Error> for Error {
     }
 }
 
+impl std::convert::From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Self {
+        Self::Bincode(err)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Clone)]
 pub struct HBaseConnection {
     address: String,
     namespace: Option<String>,
+    dry_run: bool,
 }
 
 impl HBaseConnection {
@@ -59,9 +70,26 @@ impl HBaseConnection {
         Self {
             address: address.to_string(),
             namespace: namespace.map(|ns| ns.to_string()),
+            dry_run: false,
+        }
+    }
+
+    /// A connection that never connects: puts serialize and compress cells as usual and return
+    /// the bytes they would write, and reads find nothing.
+    pub fn new_dry_run() -> Self {
+        info!("HBase: dry run, nothing will be written");
+
+        Self {
+            address: String::new(),
+            namespace: None,
+            dry_run: true,
         }
     }
 
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
     pub fn client(&self) -> HBase {
         let mut channel = TTcpChannel::new();
 
@@ -92,12 +120,31 @@ impl HBaseConnection {
     where
         T: serde::ser::Serialize,
     {
+        self.put_bincode_column_cells_with_retry(table, "bin", cells, use_compression, use_wal)
+            .await
+    }
+
+    /// Like `put_bincode_cells_with_retry`, into another column of the "x" family.
+    pub async fn put_bincode_column_cells_with_retry<T>(
+        &self,
+        table: &str,
+        column: &str,
+        cells: &[(RowKey, T)],
+        use_compression: bool,
+        use_wal: bool,
+    ) -> Result<usize>
+    where
+        T: serde::ser::Serialize,
+    {
+        if self.dry_run {
+            return encode_bincode_cells(column, cells, use_compression).map(|(_, bytes)| bytes);
+        }
         retry_notify(
             ExponentialBackoff::default(),
             || async {
                 let mut client = self.client();
                 Ok(client
-                    .put_bincode_cells(table, cells, use_compression, use_wal)
+                    .put_bincode_column_cells(table, column, cells, use_compression, use_wal)
                     .await?)
             },
             |err, _dur| {
@@ -120,6 +167,9 @@ impl HBaseConnection {
     where
         T: prost::Message,
     {
+        if self.dry_run {
+            return encode_protobuf_cells(cells, use_compression).map(|(_, bytes)| bytes);
+        }
         retry_notify(
             ExponentialBackoff::default(),
             || async {
@@ -137,6 +187,61 @@ impl HBaseConnection {
         )
         .await
     }
+
+    pub async fn get_bincode_cell_with_retry<T>(
+        &self,
+        table: &str,
+        row_key: &str,
+    ) -> Result<Option<T>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        if self.dry_run {
+            return Ok(None);
+        }
+        retry_notify(
+            ExponentialBackoff::default(),
+            || async {
+                let mut client = self.client();
+                client
+                    .get_bincode_cell(table, row_key)
+                    .await
+                    .map_err(retry_unless_decode_error)
+            },
+            |err, _dur| {
+                error!("HBase: get_bincode_cell_with_retry failed with error: {err}");
+            },
+        )
+        .await
+    }
+
+    pub async fn scan_bincode_cells_with_retry<T>(
+        &self,
+        table: &str,
+        start_row: &str,
+        stop_row: &str,
+        batch_size: i32,
+    ) -> Result<Vec<(RowKey, Result<T>)>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        if self.dry_run {
+            return Ok(vec![]);
+        }
+        retry_notify(
+            ExponentialBackoff::default(),
+            || async {
+                let mut client = self.client();
+                Ok(client
+                    .scan_bincode_cells(table, start_row, stop_row, batch_size)
+                    .await?)
+            },
+            |err, _dur| {
+                error!("HBase: scan_bincode_cells_with_retry failed with error: {err}");
+            },
+        )
+        .await
+    }
 }
 
 type InputTransport = TBufferedReadTransport<thrift::transport::ReadHalf<TTcpChannel>>;
@@ -169,20 +274,22 @@ impl HBase {
     where
         T: serde::ser::Serialize,
     {
-        let mut bytes_written = 0;
-        let mut new_row_data = vec![];
-        for (row_key, data) in cells {
-            let serialized_data = bincode::serialize(&data).unwrap();
-
-            let data = if use_compression {
-                compress_best(&serialized_data)?
-            } else {
-                compress(CompressionMethod::NoCompression, &serialized_data)?
-            };
-
-            bytes_written += data.len();
-            new_row_data.push((row_key, vec![("bin".to_string(), data)]));
-        }
+        self.put_bincode_column_cells(table, "bin", cells, use_compression, use_wal)
+            .await
+    }
+
+    pub async fn put_bincode_column_cells<T>(
+        &mut self,
+        table: &str,
+        column: &str,
+        cells: &[(RowKey, T)],
+        use_compression: bool,
+        use_wal: bool,
+    ) -> Result<usize>
+    where
+        T: serde::ser::Serialize,
+    {
+        let (new_row_data, bytes_written) = encode_bincode_cells(column, cells, use_compression)?;
 
         self.put_row_data(table, "x", &new_row_data, use_wal)
             .await?;
@@ -199,27 +306,98 @@ impl HBase {
     where
         T: prost::Message,
     {
-        let mut bytes_written = 0;
-        let mut new_row_data = vec![];
-        for (row_key, data) in cells {
-            let mut buf = Vec::with_capacity(data.encoded_len());
-            data.encode(&mut buf).unwrap();
-
-            let data = if use_compression {
-                compress_best(&buf)?
-            } else {
-                compress(CompressionMethod::NoCompression, &buf)?
-            };
-
-            bytes_written += data.len();
-            new_row_data.push((row_key, vec![("proto".to_string(), data)]));
-        }
+        let (new_row_data, bytes_written) = encode_protobuf_cells(cells, use_compression)?;
 
         self.put_row_data(table, "x", &new_row_data, use_wal)
             .await?;
         Ok(bytes_written)
     }
 
+    /// Read a single cell written by `put_bincode_cells`. Returns `None` if the row does not exist.
+    pub async fn get_bincode_cell<T>(&mut self, table: &str, row_key: &str) -> Result<Option<T>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let qualified_name = self.qualified_table_name(table);
+
+        let rows = self.client.get_row_with_columns(
+            qualified_name.as_bytes().to_vec(),
+            row_key.as_bytes().to_vec(),
+            vec![b"x:bin".to_vec()],
+            BTreeMap::new(),
+        )?;
+
+        let value = rows
+            .into_iter()
+            .next()
+            .and_then(|row| row.columns)
+            .and_then(|mut columns| columns.remove(b"x:bin".as_slice()))
+            .and_then(|cell| cell.value);
+
+        value.map(|data| decode_bincode_cell(&data)).transpose()
+    }
+
+    /// Read the cells written by `put_bincode_cells` for rows from `start_row` up to, but not
+    /// including, `stop_row`, fetching `batch_size` rows per request. Each cell is decoded on its
+    /// own, so a row that does not decode does not fail the scan.
+    pub async fn scan_bincode_cells<T>(
+        &mut self,
+        table: &str,
+        start_row: &str,
+        stop_row: &str,
+        batch_size: i32,
+    ) -> Result<Vec<(RowKey, Result<T>)>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let qualified_name = self.qualified_table_name(table);
+
+        let scanner = self.client.scanner_open_with_stop(
+            qualified_name.as_bytes().to_vec(),
+            start_row.as_bytes().to_vec(),
+            stop_row.as_bytes().to_vec(),
+            vec![b"x:bin".to_vec()],
+            BTreeMap::new(),
+        )?;
+        let cells = self.read_bincode_scanner(scanner, batch_size);
+        if let Err(e) = self.client.scanner_close(scanner) {
+            warn!("HBase: Failed to close scanner on table '{table}': {e}");
+        }
+        cells
+    }
+
+    fn read_bincode_scanner<T>(
+        &mut self,
+        scanner: ScannerID,
+        batch_size: i32,
+    ) -> Result<Vec<(RowKey, Result<T>)>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let mut cells = vec![];
+        loop {
+            let rows = self.client.scanner_get_list(scanner, batch_size)?;
+            if rows.is_empty() {
+                return Ok(cells);
+            }
+            for row in rows {
+                let Some(row_key) = row.row else {
+                    continue;
+                };
+                let value = row
+                    .columns
+                    .and_then(|mut columns| columns.remove(b"x:bin".as_slice()))
+                    .and_then(|cell| cell.value);
+                if let Some(data) = value {
+                    cells.push((
+                        String::from_utf8_lossy(&row_key).into_owned(),
+                        decode_bincode_cell(&data),
+                    ));
+                }
+            }
+        }
+    }
+
     async fn put_row_data(
         &mut self,
         table_name: &str,
@@ -263,3 +441,73 @@ impl HBase {
         }
     }
 }
+
+/// Decompress and deserialize a cell written by `put_bincode_cells`.
+fn decode_bincode_cell<T>(data: &[u8]) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    Ok(bincode::deserialize(&decompress(data)?)?)
+}
+
+/// Reads are retried on Thrift and connection errors only. A cell that fails to decompress or
+/// deserialize fails the same way on every attempt.
+fn retry_unless_decode_error(err: Error) -> backoff::Error<Error> {
+    match err {
+        Error::Io(_) | Error::Bincode(_) => backoff::Error::permanent(err),
+        Error::Thrift(_) => backoff::Error::transient(err),
+    }
+}
+
+/// Serialize cells with bincode into columns of the given name, returning the rows and their
+/// total size.
+fn encode_bincode_cells<'a, T>(
+    column: &str,
+    cells: &'a [(RowKey, T)],
+    use_compression: bool,
+) -> Result<(Vec<(&'a RowKey, RowData)>, usize)>
+where
+    T: serde::ser::Serialize,
+{
+    let mut bytes_written = 0;
+    let mut new_row_data = vec![];
+    for (row_key, data) in cells {
+        let serialized_data = bincode::serialize(&data).unwrap();
+
+        let data = if use_compression {
+            compress_best(&serialized_data)?
+        } else {
+            compress(CompressionMethod::NoCompression, &serialized_data)?
+        };
+
+        bytes_written += data.len();
+        new_row_data.push((row_key, vec![(column.to_string(), data)]));
+    }
+    Ok((new_row_data, bytes_written))
+}
+
+/// Encode protobuf cells into "proto" columns, returning the rows and their total size.
+fn encode_protobuf_cells<T>(
+    cells: &[(RowKey, T)],
+    use_compression: bool,
+) -> Result<(Vec<(&RowKey, RowData)>, usize)>
+where
+    T: prost::Message,
+{
+    let mut bytes_written = 0;
+    let mut new_row_data = vec![];
+    for (row_key, data) in cells {
+        let mut buf = Vec::with_capacity(data.encoded_len());
+        data.encode(&mut buf).unwrap();
+
+        let data = if use_compression {
+            compress_best(&buf)?
+        } else {
+            compress(CompressionMethod::NoCompression, &buf)?
+        };
+
+        bytes_written += data.len();
+        new_row_data.push((row_key, vec![("proto".to_string(), data)]));
+    }
+    Ok((new_row_data, bytes_written))
+}
@@ -1,13 +1,15 @@
 use {
     crate::{
-        file_processor::Processor, message_decoder::MessageDecoder, queue_consumer::QueueConsumer,
+        file_processor::{BatchError, BatchFailure, Processor},
+        message_decoder::MessageDecoder,
+        queue_consumer::{QueueConsumer, QueueMessage},
         queue_producer::QueueProducer,
     },
     anyhow::Result,
     bytes::BytesMut,
     log::{error, info},
-    serde_json::json,
-    std::sync::Arc,
+    serde_json::{json, Value},
+    std::{str, sync::Arc},
 };
 
 pub struct Ingestor<C, P> {
@@ -42,19 +44,30 @@ where
         while let Some(msg_result) = self.consumer.next_message().await {
             match msg_result {
                 Ok(queue_message) => {
-                    let payload_str = queue_message.internal();
+                    let payload = &queue_message.payload;
 
-                    if !payload_str.is_empty() {
-                        match self.decoder.decode(payload_str.as_bytes()).await {
+                    if !payload.is_empty() {
+                        match self.decoder.decode(payload, &queue_message.headers).await {
                             Ok(decoded) => {
                                 // Process the decoded payload
                                 if let Err(e) = self.processor.process_decoded(decoded).await {
                                     error!("Error processing payload: {:?}", e);
-                                    self.send_to_dead_letter(
-                                        payload_str.as_bytes(),
-                                        &e.to_string(),
-                                    )
-                                    .await;
+                                    match e.downcast_ref::<BatchError>() {
+                                        // Only the failed blocks of a batch are dead-lettered
+                                        Some(batch_error) => {
+                                            for failure in &batch_error.failures {
+                                                self.send_block_to_dead_letter(
+                                                    &queue_message,
+                                                    failure,
+                                                )
+                                                .await;
+                                            }
+                                        }
+                                        None => {
+                                            self.send_to_dead_letter(&queue_message, &e.to_string())
+                                                .await;
+                                        }
+                                    }
                                 }
 
                                 if let Err(e) = self.consumer.commit(&queue_message).await {
@@ -63,11 +76,8 @@ where
                             }
                             Err(decode_err) => {
                                 error!("Failed to decode payload: {:?}", decode_err);
-                                self.send_to_dead_letter(
-                                    payload_str.as_bytes(),
-                                    &decode_err.to_string(),
-                                )
-                                .await;
+                                self.send_to_dead_letter(&queue_message, &decode_err.to_string())
+                                    .await;
                             }
                         }
                     } else {
@@ -83,12 +93,31 @@ where
         Ok(())
     }
 
-    async fn send_to_dead_letter(&self, msg: &[u8], error_str: &str) {
+    async fn send_to_dead_letter(&self, msg: &QueueMessage, error_str: &str) {
+        // Binary payloads cannot be embedded as a JSON string, so they are base64 encoded
+        let mut dlq_payload = match str::from_utf8(&msg.payload) {
+            Ok(text) => json!({ "message": text }),
+            Err(_) => json!({ "message_base64": base64::encode(&msg.payload) }),
+        };
+        dlq_payload["error"] = json!(error_str);
+        self.produce_dead_letter(msg, dlq_payload).await;
+    }
+
+    async fn send_block_to_dead_letter(&self, msg: &QueueMessage, failure: &BatchFailure) {
         let dlq_payload = json!({
-            "message": String::from_utf8_lossy(msg),
-            "error": error_str,
-        })
-        .to_string();
+            "message": failure.raw,
+            "error": format!("{:#}", failure.error),
+            "batch_index": failure.index,
+            "slot": failure.slot,
+        });
+        self.produce_dead_letter(msg, dlq_payload).await;
+    }
+
+    async fn produce_dead_letter(&self, msg: &QueueMessage, mut dlq_payload: Value) {
+        dlq_payload["topic"] = json!(msg.topic);
+        dlq_payload["partition"] = json!(msg.partition);
+        dlq_payload["offset"] = json!(msg.offset);
+        let dlq_payload = dlq_payload.to_string();
 
         let payload_bytes = BytesMut::from(dlq_payload.as_str());
         if let Err(e) = self.producer.produce_message(payload_bytes, None).await {
@@ -1,6 +1,6 @@
 use {
     anyhow::{anyhow, Result},
-    serde::de::DeserializeOwned,
+    serde::{de::DeserializeOwned, Deserialize, Deserializer},
     serde_json::{self, Value},
 };
 
@@ -9,28 +9,187 @@ pub fn from_value_with_path<T>(value: Value, type_name: &'static str) -> Result<
 where
     T: DeserializeOwned,
 {
-    // Use serde_path_to_error to track the path where deserialization fails
-    let json_str = match serde_json::to_string(&value) {
-        Ok(s) => s,
-        Err(e) => {
-            return Err(anyhow!(
-                "Failed to serialize intermediate JSON value for {type_name}: {e}"
-            ))
-        }
+    deserialize_with_path(value, type_name)
+}
+
+/// Deserialize JSON text into T without an intermediate `Value`, including a precise JSON path
+/// on error. Borrowed fields of T borrow from the text.
+pub fn from_str_with_path<'a, T>(json: &'a str, type_name: &'static str) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let value = deserialize_with_path(&mut deserializer, type_name)?;
+    deserializer
+        .end()
+        .map_err(|e| anyhow!("Trailing characters after {type_name}: {e}"))?;
+    Ok(value)
+}
+
+/// A failed `from_slice_fast_with_path`: the error, and the text parsed as a `Value` if it is
+/// valid JSON, so that callers can retry without reading the text again.
+pub struct FastParseError {
+    pub error: anyhow::Error,
+    pub value: Option<Value>,
+}
+
+/// Returned by `from_slice_fast_with_path` for text that deserializes, but only by ignoring
+/// some of its keys, e.g. legacy or unknown fields.
+#[derive(Debug, thiserror::Error)]
+#[error("{type_name} ignores keys at paths: {}", paths.join(", "))]
+pub struct IgnoredKeysError {
+    pub type_name: &'static str,
+    pub paths: Vec<String>,
+}
+
+/// Deserialize JSON text into T like `from_str_with_path`, parsing it in place. With the
+/// `simd-json` feature the text is parsed with simd-json, which rewrites escaped strings in the
+/// buffer, so the buffer must not be read as text afterwards. If T does not deserialize, the
+/// document simd-json already parsed is turned into a `Value` to report the error with its path.
+/// Text with keys that T ignores fails with an `IgnoredKeysError`, so that callers can decide
+/// what to do with them.
+pub fn from_slice_fast_with_path<T>(
+    json: &mut [u8],
+    type_name: &'static str,
+) -> std::result::Result<T, FastParseError>
+where
+    T: DeserializeOwned,
+{
+    #[cfg(feature = "simd-json")]
+    return simd::from_slice(json, type_name);
+
+    #[cfg(not(feature = "simd-json"))]
+    {
+        let mut ignored = vec![];
+        let parsed = std::str::from_utf8(json)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| {
+                let mut deserializer = serde_json::Deserializer::from_str(json);
+                let value = deserialize_with_path(
+                    serde_ignored::Deserializer::new(&mut deserializer, &mut |path| {
+                        ignored.push(path.to_string())
+                    }),
+                    type_name,
+                )?;
+                deserializer
+                    .end()
+                    .map_err(|e| anyhow!("Trailing characters after {type_name}: {e}"))?;
+                Ok(value)
+            })
+            .and_then(|value| {
+                if !ignored.is_empty() {
+                    return Err(IgnoredKeysError {
+                        type_name,
+                        paths: ignored,
+                    }
+                    .into());
+                }
+                Ok(value)
+            });
+        parsed.map_err(|error| FastParseError {
+            error,
+            value: serde_json::from_slice(json).ok(),
+        })
+    }
+}
+
+#[cfg(feature = "simd-json")]
+mod simd {
+    use {
+        super::{deserialize_with_path, FastParseError, IgnoredKeysError},
+        anyhow::anyhow,
+        serde::{de::DeserializeOwned, Deserialize},
+        serde_json::Value,
+        simd_json::{Buffers, Deserializer},
+        std::cell::RefCell,
     };
-    let mut deserializer = serde_json::Deserializer::from_str(&json_str);
-    match serde_path_to_error::deserialize::<_, T>(&mut deserializer) {
-        Ok(v) => Ok(v),
-        Err(err) => {
-            let path = err.path().to_string();
-            let tx_idx_hint = extract_tx_index_from_path_string(&path)
-                .map(|i| format!(" (transaction index: {i})"))
-                .unwrap_or_default();
-            Err(anyhow!(
-                "Deserialization error for {type_name} at path `{path}`{tx_idx_hint}: {}",
-                err.inner()
-            ))
+
+    thread_local! {
+        // The parser's buffers are kept per thread to avoid allocating them for every block
+        static BUFFERS: RefCell<Buffers> = RefCell::new(Buffers::default());
+    }
+
+    pub fn from_slice<T: DeserializeOwned>(
+        json: &mut [u8],
+        type_name: &'static str,
+    ) -> Result<T, FastParseError> {
+        BUFFERS.with(|buffers| {
+            let mut deserializer =
+                Deserializer::from_slice_with_buffers(json, &mut buffers.borrow_mut()).map_err(
+                    |e| FastParseError {
+                        error: anyhow!("Failed to parse {type_name} JSON: {e}"),
+                        value: None,
+                    },
+                )?;
+            let mut ignored = vec![];
+            let ignored_keys = match serde_ignored::deserialize(&mut deserializer, |path| {
+                ignored.push(path.to_string())
+            }) {
+                Ok(value) if ignored.is_empty() => return Ok(value),
+                Ok(_) => Some(IgnoredKeysError {
+                    type_name,
+                    paths: ignored,
+                }),
+                Err(_) => None,
+            };
+            deserializer.restart();
+            let value = Value::deserialize(&mut deserializer).map_err(|e| FastParseError {
+                error: anyhow!("Failed to parse {type_name} JSON: {e}"),
+                value: None,
+            })?;
+            if let Some(ignored_keys) = ignored_keys {
+                return Err(FastParseError {
+                    error: ignored_keys.into(),
+                    value: Some(value),
+                });
+            }
+            deserialize_with_path(&value, type_name).map_err(|error| FastParseError {
+                error,
+                value: Some(value),
+            })
+        })
+    }
+}
+
+/// Deserialize T from any serde_json deserializer, e.g. a `Value`, a `&Value` or JSON text,
+/// and include a precise JSON path on error.
+pub fn deserialize_with_path<'de, D, T>(deserializer: D, type_name: &'static str) -> Result<T>
+where
+    D: Deserializer<'de, Error = serde_json::Error>,
+    T: Deserialize<'de>,
+{
+    // Use serde_path_to_error to track the path where deserialization fails
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        let path = err.path().to_string();
+        JsonPathError {
+            type_name,
+            transaction_index: extract_tx_index_from_path_string(&path),
+            path,
+            message: err.inner().to_string(),
         }
+        .into()
+    })
+}
+
+/// Returned by the `*_with_path` functions when JSON does not deserialize.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Deserialization error for {type_name} at path `{path}`{}: {message}",
+    transaction_index.map(|i| format!(" (transaction index: {i})")).unwrap_or_default()
+)]
+pub struct JsonPathError {
+    pub type_name: &'static str,
+    /// Path of the value that failed, e.g. `transactions[5].meta.fee`.
+    pub path: String,
+    /// Transaction index found in the path, if any.
+    pub transaction_index: Option<usize>,
+    pub message: String,
+}
+
+impl JsonPathError {
+    /// Find the deserialization error among the causes of an error.
+    pub fn find(error: &anyhow::Error) -> Option<&JsonPathError> {
+        error.chain().find_map(|cause| cause.downcast_ref())
     }
 }
 
@@ -54,4 +213,199 @@ fn extract_tx_index_from_path_string(path: &str) -> Option<usize> {
     None
 }
 
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_block_decoder::block::encoded_block::EncodedConfirmedBlock};
+
+    /// A vote transaction and a token transfer, with escaped log messages, the largest lamport
+    /// balance, token amounts as floats and a negative rent reward.
+    const BLOCK: &str = r#"{
+        "previousBlockhash": "JCu1pPv9ie31W2iiCfbgw24dE6Qhrd8hSqyfuTX72KA9",
+        "blockhash": "13VwGEw4m1HcZeSVqxJvVYZu91DV2Jkk7sY5uv6WCHn9",
+        "parentSlot": 299999999,
+        "transactions": [
+            {
+                "meta": {
+                    "computeUnitsConsumed": 2100,
+                    "err": null,
+                    "fee": 5000,
+                    "innerInstructions": [],
+                    "loadedAddresses": {"readonly": [], "writable": []},
+                    "logMessages": [
+                        "Program Vote111111111111111111111111111111111111111 invoke [1]",
+                        "Program Vote111111111111111111111111111111111111111 success"
+                    ],
+                    "postBalances": [1499995000, 27074400, 1],
+                    "postTokenBalances": [],
+                    "preBalances": [1500000000, 27074400, 1],
+                    "preTokenBalances": [],
+                    "rewards": [],
+                    "status": {"Ok": null}
+                },
+                "transaction": {
+                    "message": {
+                        "accountKeys": [
+                            "111111116Jswqk47s9PUcyCc88MMVwzgvHN64KPN",
+                            "11111111BcktgV7EjHmxEwQDFFhhztzNqZjB7dmj",
+                            "Vote111111111111111111111111111111111111111"
+                        ],
+                        "header": {
+                            "numReadonlySignedAccounts": 0,
+                            "numReadonlyUnsignedAccounts": 1,
+                            "numRequiredSignatures": 1
+                        },
+                        "instructions": [
+                            {
+                                "accounts": [1, 0],
+                                "data": "14xVP3bfCbciq5f5dEnZkpwEju491ugrrTZfiyAKXnTyCJKztZJXT1Zs24A5HKBV3hDiRBoCXKe1X4VtjS7V4k93z6QVkcakThn2fQpk9qEydmvJq7aPFg1iYoS3XYZR1wduPtJ8ZFxB2sxf3QGhsx",
+                                "programIdIndex": 2,
+                                "stackHeight": null
+                            }
+                        ],
+                        "recentBlockhash": "13VwGEw4m1HcexKSghN3MgxNkyR69S76cpXmqCTbFcAW"
+                    },
+                    "signatures": [
+                        "111111115VBjSR1ZTZWLP2zeCGMcez9n9b2ocCzKk1RU6Jdr5rbHoUfA7YkWobKRuybEm9CFBHX9WhmMLuETW"
+                    ]
+                },
+                "version": "legacy"
+            },
+            {
+                "meta": {
+                    "computeUnitsConsumed": 4645,
+                    "err": null,
+                    "fee": 10000,
+                    "innerInstructions": [
+                        {
+                            "index": 0,
+                            "instructions": [
+                                {"accounts": [1, 2, 0], "data": "3Bxs4h24hBtQy9rw", "programIdIndex": 3, "stackHeight": 2}
+                            ]
+                        }
+                    ],
+                    "loadedAddresses": {"readonly": [], "writable": []},
+                    "logMessages": [
+                        "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [1]",
+                        "Program log: Memo (len 42): \"quoted\", back\\slash, tab\there, line\nbreak",
+                        "Program log: café 🚀 \/ \u0000 \u001f",
+                        "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA success"
+                    ],
+                    "postBalances": [18446744073709551615, 2039280, 2039280, 934087680],
+                    "postTokenBalances": [
+                        {
+                            "accountIndex": 1,
+                            "mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                            "owner": "11111111BcktgV7EjHmxEwQDFFhhztzNqZjB7dmj",
+                            "programId": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+                            "uiTokenAmount": {
+                                "amount": "18446744073709551615",
+                                "decimals": 6,
+                                "uiAmount": 18446744073709.55,
+                                "uiAmountString": "18446744073709.551615"
+                            }
+                        }
+                    ],
+                    "preBalances": [18446744073709541615, 2039280, 2039280, 934087680],
+                    "preTokenBalances": [
+                        {
+                            "accountIndex": 1,
+                            "mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                            "owner": "11111111BcktgV7EjHmxEwQDFFhhztzNqZjB7dmj",
+                            "programId": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+                            "uiTokenAmount": {
+                                "amount": "1",
+                                "decimals": 6,
+                                "uiAmount": 1e-6,
+                                "uiAmountString": "0.000001"
+                            }
+                        }
+                    ],
+                    "rewards": [],
+                    "status": {"Ok": null}
+                },
+                "transaction": {
+                    "message": {
+                        "accountKeys": [
+                            "111111116Jswqk47s9PUcyCc88MMVwzgvHN64KPN",
+                            "11111111BcktgV7EjHmxEwQDFFhhztzNqZjB7dmj",
+                            "11111111CdVmijpd7Q6zYgBcoRoPcpEyDYfnfZyB",
+                            "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+                        ],
+                        "header": {
+                            "numReadonlySignedAccounts": 0,
+                            "numReadonlyUnsignedAccounts": 1,
+                            "numRequiredSignatures": 1
+                        },
+                        "instructions": [
+                            {"accounts": [1, 2, 0], "data": "3Bxs4h24hBtQy9rw", "programIdIndex": 3, "stackHeight": null}
+                        ],
+                        "recentBlockhash": "13VwGEw4m1HcexKSghN3MgxNkyR69S76cpXmqCTbFcAW"
+                    },
+                    "signatures": [
+                        "111111115VBjSR1ZTZWLP2zeCGMcez9n9b2ocCzKk1RU6Jdr5rbHoUfA7YkWobKRuybEm9CFBHX9WhmMLuETX"
+                    ]
+                },
+                "version": 0
+            }
+        ],
+        "rewards": [
+            {"commission": null, "lamports": 12345678, "postBalance": 987654321000, "pubkey": "13VwGEw4m1Ha11r2KAocHViZmscXakvr9KgpC1v4bQz1", "rewardType": "Fee"},
+            {"commission": 10, "lamports": -5000, "postBalance": 0, "pubkey": "11111111BcktgV7EjHmxEwQDFFhhztzNqZjB7dmj", "rewardType": "Rent"}
+        ],
+        "numPartitions": null,
+        "blockTime": 1730000000,
+        "blockHeight": 280000000
+    }"#;
+
+    fn fast<T: DeserializeOwned>(json: &str) -> std::result::Result<T, FastParseError> {
+        from_slice_fast_with_path(&mut json.as_bytes().to_vec(), "T")
+    }
+
+    #[test]
+    fn fast_parse_matches_serde_json_on_blocks() {
+        let expected: EncodedConfirmedBlock = serde_json::from_str(BLOCK).unwrap();
+        let actual: EncodedConfirmedBlock = fast(BLOCK).unwrap_or_else(|e| panic!("{:#}", e.error));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn fast_parse_matches_serde_json_on_values() {
+        for json in [
+            BLOCK,
+            r#"[0, -1, 18446744073709551615, -9223372036854775808, 18446744073709551616]"#,
+            r#"[123456789012345678901234567890, 0.1, -2.5e-324, 1.7976931348623157e308, 1E3]"#,
+            r#"{"escapes": "\"\\\/\b\f\n\r\té🚀", "empty": "", "nested": [[{}]]}"#,
+        ] {
+            let expected: Value = serde_json::from_str(json).unwrap();
+            let actual: Value = fast(json).unwrap_or_else(|e| panic!("{json}: {:#}", e.error));
+            assert_eq!(actual, expected, "{json}");
+        }
+    }
 
+    #[test]
+    fn fast_parse_errors_have_the_path_and_the_parsed_value() {
+        let json = BLOCK.replacen(r#""fee": 10000"#, r#""fee": "10000""#, 1);
+        let FastParseError { error, value } = fast::<EncodedConfirmedBlock>(&json).err().unwrap();
+        let expected = from_str_with_path::<EncodedConfirmedBlock>(&json, "T").unwrap_err();
+        assert_eq!(
+            JsonPathError::find(&error).map(|e| &e.path),
+            JsonPathError::find(&expected).map(|e| &e.path),
+        );
+        assert_eq!(value, serde_json::from_str(&json).ok());
+    }
+
+    #[test]
+    fn fast_parse_reports_ignored_keys() {
+        let json = BLOCK.replacen(r#""blockTime""#, r#""block_time""#, 1);
+        let FastParseError { error, value } = fast::<EncodedConfirmedBlock>(&json).err().unwrap();
+        let ignored = error.downcast_ref::<IgnoredKeysError>().unwrap();
+        assert_eq!(ignored.paths, vec!["block_time".to_string()]);
+        assert_eq!(value, serde_json::from_str(&json).ok());
+    }
+
+    #[test]
+    fn fast_parse_of_invalid_json_has_no_value() {
+        let FastParseError { value, .. } = fast::<Value>(&BLOCK[..BLOCK.len() - 1]).err().unwrap();
+        assert!(value.is_none());
+    }
+}
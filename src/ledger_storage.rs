@@ -3,8 +3,9 @@ use xxhash_rust::{xxh3::xxh3_128, xxh32::xxh32};
 use {
     crate::hbase::{Error as HBaseError, HBaseConnection},
     agave_reserved_account_keys::ReservedAccountKeys,
+    anyhow::anyhow,
     dexter_storage_proto_tx::convert::generated,
-    log::{debug, error, info},
+    log::{debug, error, info, warn},
     memcache::{Client, MemcacheError},
     serde::{Deserialize, Serialize},
     solana_hash::Hash,
@@ -37,14 +38,14 @@ use {
     //     },
     // },
     solana_transaction_status::{
-        // ConfirmedBlock,
+        ConfirmedBlock,
         TransactionStatusMeta,
         TransactionWithStatusMeta,
         // VersionedTransactionWithStatusMeta,
         // Reward,
     },
     std::{
-        collections::{HashMap, HashSet},
+        collections::{BTreeMap, HashMap, HashSet},
         str::FromStr,
     },
     thiserror::Error,
@@ -67,6 +68,17 @@ pub enum Error {
 
     #[error("Protobuf error: {0}")]
     EncodingError(prost::EncodeError),
+
+    #[error(
+        "Block at slot {slot} does not chain to its parent slot {parent_slot}: previous \
+         blockhash {previous_blockhash}, stored parent blockhash {stored_blockhash}"
+    )]
+    ParentChainMismatch {
+        slot: Slot,
+        parent_slot: Slot,
+        previous_blockhash: String,
+        stored_blockhash: String,
+    },
 }
 
 impl std::convert::From<HBaseError> for Error {
@@ -101,7 +113,11 @@ impl From<TaskError> for Error {
 pub type Result<T> = std::result::Result<T, Error>;
 
 enum TaskResult {
-    BytesWritten(usize),
+    RowsWritten {
+        table_name: String,
+        rows: usize,
+        bytes: usize,
+    },
     CachedTransactions(usize),
 }
 
@@ -180,6 +196,10 @@ fn slot_to_key(slot: Slot) -> String {
     format!("{slot:016x}")
 }
 
+fn key_to_slot(key: &str) -> Option<Slot> {
+    Slot::from_str_radix(key, 16).ok()
+}
+
 fn slot_to_blocks_key(slot: Slot, use_md5: bool) -> String {
     let slot_hex = slot_to_key(slot);
 
@@ -231,6 +251,30 @@ pub struct IngestorIndexingProgress {
     pub entries_count: Option<u64>,
 }
 
+/// Column of the indexing progress table holding `IngestorBlockCompleteness`. Progress rows are
+/// bincode, which does not describe its own layout, so anything added to a block's progress goes
+/// into its own column rather than changing `IngestorIndexingProgress`.
+pub const BLOCK_COMPLETENESS_COLUMN: &str = "completeness";
+
+/// What a stored block is missing, written next to its indexing progress. Rows written before
+/// this column existed have no completeness cell.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default)]
+pub struct IngestorBlockCompleteness {
+    /// Transactions stored without status metadata.
+    pub missing_meta_count: u64,
+    /// Transactions stored with synthesized empty status metadata. Their status, fee and
+    /// balances are not real.
+    pub synthesized_meta_count: u64,
+    /// The block was stored without its transactions, as fetched with transactionDetails=none.
+    pub transactions_omitted: bool,
+}
+
+impl IngestorBlockCompleteness {
+    pub fn is_complete(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct StoredConfirmedTransactionWithStatusMeta {
     pub slot: Slot,
@@ -305,8 +349,10 @@ pub const TX_BY_ADDR_TABLE_NAME: &str = "tx-by-addr";
 pub const FULL_TX_TABLE_NAME: &str = "tx_full";
 pub const ENTRIES_TABLE_NAME: &str = "entries";
 pub const INDEXING_PROGRESS_TABLE_NAME: &str = "ingestor_indexing_progress";
+pub const QUARANTINE_TABLE_NAME: &str = "quarantined_blocks";
 pub const DEFAULT_MEMCACHE_ADDRESS: &str = "127.0.0.1:11211";
 pub const DEFAULT_MEMCACHE_TIMEOUT_SECS: u64 = 1;
+const INDEXING_PROGRESS_SCAN_BATCH_SIZE: i32 = 1000;
 
 #[derive(Debug)]
 pub struct LedgerStorageConfig {
@@ -382,6 +428,39 @@ pub struct UploaderConfig {
     pub entries_table_name: String,
     pub indexing_progress_table_name: String,
     pub disable_tx_filter_block_boundary: bool,
+    pub parent_chain_policy: ParentChainPolicy,
+    pub quarantine_table_name: String,
+}
+
+/// What to do with a block whose previous blockhash differs from the blockhash stored in indexing
+/// progress for its parent slot: the block is from a minority fork, or one of them is corrupt.
+/// Blocks whose parent has no indexing progress are not checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParentChainPolicy {
+    /// Do not look up the parent.
+    #[default]
+    Off,
+    /// Fail the upload.
+    Reject,
+    /// Log a warning and upload the block, replacing anything stored for its slot.
+    Overwrite,
+    /// Log a warning and store the block in the quarantine table only, keyed by slot and
+    /// blockhash.
+    Quarantine,
+}
+
+impl FromStr for ParentChainPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(ParentChainPolicy::Off),
+            "reject" => Ok(ParentChainPolicy::Reject),
+            "overwrite" => Ok(ParentChainPolicy::Overwrite),
+            "quarantine" => Ok(ParentChainPolicy::Quarantine),
+            other => Err(anyhow!("Unsupported parent chain policy: {other}")),
+        }
+    }
 }
 
 impl Default for UploaderConfig {
@@ -419,6 +498,8 @@ impl Default for UploaderConfig {
             entries_table_name: ENTRIES_TABLE_NAME.to_string(),
             indexing_progress_table_name: INDEXING_PROGRESS_TABLE_NAME.to_string(),
             disable_tx_filter_block_boundary: false,
+            parent_chain_policy: ParentChainPolicy::default(),
+            quarantine_table_name: QUARANTINE_TABLE_NAME.to_string(),
         }
     }
 }
@@ -429,6 +510,51 @@ pub struct FilterTxIncludeExclude {
     pub addrs: HashSet<Pubkey>,
 }
 
+/// Rows and compressed bytes written to one table.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct TableWrite {
+    pub rows: usize,
+    pub bytes: usize,
+}
+
+/// What the upload of one block wrote, or would have written in a dry run.
+#[derive(Debug, Default, Serialize)]
+pub struct UploadReport {
+    pub slot: Slot,
+    /// Rows and compressed bytes by table name.
+    pub tables: BTreeMap<String, TableWrite>,
+    /// Signatures of transactions left out of a table, by table name and the filter that dropped
+    /// them. Only recorded in dry runs.
+    pub dropped_transactions: BTreeMap<String, BTreeMap<&'static str, Vec<String>>>,
+    /// Addresses left out of the tx-by-addr index, by filter. Only recorded in dry runs.
+    pub dropped_addresses: BTreeMap<&'static str, u64>,
+    /// Whether the block was stored in the quarantine table instead, see `ParentChainPolicy`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub quarantined: bool,
+}
+
+impl UploadReport {
+    fn record_write(&mut self, table_name: &str, rows: usize, bytes: usize) {
+        let write = self.tables.entry(table_name.to_string()).or_default();
+        write.rows += rows;
+        write.bytes += bytes;
+    }
+
+    fn record_dropped_transaction(
+        &mut self,
+        table_name: &str,
+        filter: &'static str,
+        signature: &Signature,
+    ) {
+        self.dropped_transactions
+            .entry(table_name.to_string())
+            .or_default()
+            .entry(filter)
+            .or_default()
+            .push(signature.to_string());
+    }
+}
+
 #[derive(Clone)]
 pub struct LedgerStorage {
     connection: HBaseConnection,
@@ -481,6 +607,64 @@ impl LedgerStorage {
         }
     }
 
+    /// Storage that runs the whole upload, including conversion, filtering and cell encoding,
+    /// without writing to HBase or the transaction cache.
+    pub fn new_dry_run(uploader_config: UploaderConfig) -> Self {
+        Self {
+            connection: HBaseConnection::new_dry_run(),
+            uploader_config,
+            cache_client: None,
+            enable_full_tx_cache: false,
+            tx_cache_expiration: None,
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.connection.is_dry_run()
+    }
+
+    /// Whether uploads look up the indexing progress of the parent slot, see `ParentChainPolicy`.
+    pub fn checks_parent_chain(&self) -> bool {
+        self.uploader_config.parent_chain_policy != ParentChainPolicy::Off
+    }
+
+    /// Indexing progress rows of the slots from `start_slot` to `end_slot` inclusive, in slot
+    /// order. Slots that were never ingested have no row. Each row is decoded on its own: a row
+    /// that does not decode is logged and returned without its progress, so that the slot still
+    /// counts as stored.
+    pub async fn get_indexing_progress_range(
+        &self,
+        start_slot: Slot,
+        end_slot: Slot,
+    ) -> Result<Vec<(Slot, Option<IngestorIndexingProgress>)>> {
+        // An empty stop row scans to the end of the table
+        let stop_row = end_slot.checked_add(1).map(slot_to_key).unwrap_or_default();
+        let cells = self
+            .connection
+            .scan_bincode_cells_with_retry::<IngestorIndexingProgress>(
+                self.uploader_config.indexing_progress_table_name.as_str(),
+                &slot_to_key(start_slot),
+                &stop_row,
+                INDEXING_PROGRESS_SCAN_BATCH_SIZE,
+            )
+            .await?;
+        Ok(cells
+            .into_iter()
+            .filter_map(|(row_key, progress)| {
+                let Some(slot) = key_to_slot(&row_key) else {
+                    warn!("HBase: skipping indexing progress row with invalid key {row_key:?}");
+                    return None;
+                };
+                let progress = progress
+                    .inspect_err(|err| {
+                        warn!("HBase: indexing progress for slot {slot} does not decode: {err}");
+                    })
+                    .ok();
+                Some((slot, progress))
+            })
+            .collect())
+    }
+
     pub async fn upload_confirmed_block(
         &self,
         slot: Slot,
@@ -506,6 +690,52 @@ impl LedgerStorage {
             block: confirmed_block,
             entries,
         } = confirmed_block_with_entries;
+        self.upload_block_with_entries(slot, confirmed_block.into(), entries, 0, false)
+            .await
+            .map(|_| ())
+    }
+
+    /// Upload a block whose transactions may lack status metadata. Transactions without metadata
+    /// are stored in the block only: their status is unknown, so they are left out of the tx,
+    /// tx-by-addr and full tx tables. `synthesized_meta_count` is the number of transactions whose
+    /// metadata was synthesized before upload, and `transactions_omitted` tells that the block was
+    /// stored without its transactions. Both are recorded next to indexing progress with the number
+    /// of transactions without metadata, see `IngestorBlockCompleteness`.
+    /// Returns the rows written per table; in dry runs also the transactions filters dropped.
+    pub async fn upload_block_with_entries(
+        &self,
+        slot: Slot,
+        confirmed_block: ConfirmedBlock,
+        entries: Vec<EntrySummary>,
+        synthesized_meta_count: u64,
+        transactions_omitted: bool,
+    ) -> Result<UploadReport> {
+        let dry_run = self.is_dry_run();
+        let mut report = UploadReport {
+            slot,
+            ..UploadReport::default()
+        };
+
+        if let Some(stored_blockhash) =
+            self.find_parent_chain_mismatch(slot, &confirmed_block).await?
+        {
+            let mismatch = Error::ParentChainMismatch {
+                slot,
+                parent_slot: confirmed_block.parent_slot,
+                previous_blockhash: confirmed_block.previous_blockhash.clone(),
+                stored_blockhash,
+            };
+            match self.uploader_config.parent_chain_policy {
+                ParentChainPolicy::Off | ParentChainPolicy::Reject => return Err(mismatch),
+                ParentChainPolicy::Overwrite => {
+                    warn!("HBase: {mismatch}, uploading it anyway");
+                }
+                ParentChainPolicy::Quarantine => {
+                    warn!("HBase: {mismatch}, quarantining it");
+                    return self.quarantine_block(slot, confirmed_block).await;
+                }
+            }
+        }
         let mut by_addr: HashMap<&Pubkey, Vec<TransactionByAddrInfo>> = HashMap::new();
 
         info!(
@@ -517,7 +747,13 @@ impl LedgerStorage {
         let mut tx_cells = vec![];
         let mut full_tx_cells = vec![];
         let mut full_tx_cache = vec![];
+        let mut missing_meta_count = 0u64;
         for (index, transaction_with_meta) in confirmed_block.transactions.iter().enumerate() {
+            let TransactionWithStatusMeta::Complete(transaction_with_meta) = transaction_with_meta
+            else {
+                missing_meta_count += 1;
+                continue;
+            };
             let VersionedTransactionWithStatusMeta { meta, transaction } = transaction_with_meta;
             let err = meta.status.clone().err();
             let index = index as u32;
@@ -583,18 +819,32 @@ impl LedgerStorage {
 
             let combined_keys = get_account_keys(&transaction_with_meta);
 
+            let skipped_full_tx_before_addresses = should_skip_full_tx;
+
             if !should_skip_tx_by_addr {
                 for address in transaction_with_meta.account_keys().iter() {
                     // Filter program accounts from tx-by-addr index
                     if self.uploader_config.filter_program_accounts
                         && is_program_account(address, transaction_with_meta, &combined_keys)
                     {
+                        if dry_run {
+                            *report
+                                .dropped_addresses
+                                .entry("filter_program_accounts")
+                                .or_default() += 1;
+                        }
                         continue;
                     }
 
                     if self.uploader_config.filter_readonly_accounts
                         && is_readonly_account(address, transaction_with_meta)
                     {
+                        if dry_run {
+                            *report
+                                .dropped_addresses
+                                .entry("filter_readonly_accounts")
+                                .or_default() += 1;
+                        }
                         continue;
                     }
 
@@ -602,6 +852,14 @@ impl LedgerStorage {
                         should_skip_full_tx = true;
                     }
 
+                    if dry_run {
+                        if reserved_account_keys.is_reserved(address) {
+                            *report.dropped_addresses.entry("reserved_account").or_default() += 1;
+                        } else if !self.should_include_in_tx_by_addr(address) {
+                            *report.dropped_addresses.entry("tx_by_addr_filter").or_default() += 1;
+                        }
+                    }
+
                     if !reserved_account_keys.is_reserved(address)
                         && self.should_include_in_tx_by_addr(address)
                     {
@@ -658,6 +916,53 @@ impl LedgerStorage {
                 should_skip_tx = false;
             }
 
+            if dry_run {
+                let config = &self.uploader_config;
+                let filter = |table_voting_filter, table_error_filter| {
+                    self.transaction_filter(
+                        is_voting,
+                        is_error,
+                        table_voting_filter,
+                        table_error_filter,
+                    )
+                };
+                if !config.disable_tx && should_skip_tx {
+                    report.record_dropped_transaction(
+                        &config.tx_table_name,
+                        filter(
+                            ("filter_tx_voting", config.filter_tx_voting),
+                            ("filter_tx_error", config.filter_tx_error),
+                        ),
+                        &signature,
+                    );
+                }
+                if !config.disable_tx_by_addr && should_skip_tx_by_addr {
+                    report.record_dropped_transaction(
+                        &config.tx_by_addr_table_name,
+                        filter(
+                            ("filter_tx_by_addr_voting", config.filter_tx_by_addr_voting),
+                            ("filter_tx_by_addr_error", config.filter_tx_by_addr_error),
+                        ),
+                        &signature,
+                    );
+                }
+                if config.enable_full_tx && should_skip_full_tx {
+                    let filter = if skipped_full_tx_before_addresses {
+                        filter(
+                            ("filter_tx_full_voting", config.filter_tx_full_voting),
+                            ("filter_tx_full_error", config.filter_tx_full_error),
+                        )
+                    } else {
+                        "tx_full_filter"
+                    };
+                    report.record_dropped_transaction(
+                        &config.full_tx_table_name,
+                        filter,
+                        &signature,
+                    );
+                }
+            }
+
             if !self.uploader_config.disable_tx && !should_skip_tx {
                 tx_cells.push((
                     signature.to_string(),
@@ -710,7 +1015,11 @@ impl LedgerStorage {
                     write_to_wal,
                 )
                 .await
-                .map(TaskResult::BytesWritten)
+                .map(|bytes| TaskResult::RowsWritten {
+                    table_name: full_tx_table_name,
+                    rows: full_tx_cells.len(),
+                    bytes,
+                })
                 .map_err(|e| TaskErrorWithType::new(TaskType::UploadFullTx, e))
             }));
         }
@@ -756,7 +1065,11 @@ impl LedgerStorage {
                     write_to_wal,
                 )
                 .await
-                .map(TaskResult::BytesWritten)
+                .map(|bytes| TaskResult::RowsWritten {
+                    table_name: tx_table_name,
+                    rows: tx_cells.len(),
+                    bytes,
+                })
                 .map_err(|e| TaskErrorWithType::new(TaskType::UploadTx, e))
             }));
         }
@@ -778,7 +1091,11 @@ impl LedgerStorage {
                     write_to_wal,
                 )
                 .await
-                .map(TaskResult::BytesWritten)
+                .map(|bytes| TaskResult::RowsWritten {
+                    table_name: tx_by_addr_table_name,
+                    rows: tx_by_addr_cells.len(),
+                    bytes,
+                })
                 .map_err(|e| TaskErrorWithType::new(TaskType::UploadTxByAddr, e))
             }));
         }
@@ -806,13 +1123,16 @@ impl LedgerStorage {
                         write_to_wal,
                     )
                     .await
-                    .map(TaskResult::BytesWritten)
+                    .map(|bytes| TaskResult::RowsWritten {
+                        table_name: entries_table_name,
+                        rows: 1,
+                        bytes,
+                    })
                     .map_err(|e| TaskErrorWithType::new(TaskType::UploadEntries, e))
                 }));
             }
         }
 
-        let mut _bytes_written = 0;
         let mut total_cached_transactions = 0;
         let mut maybe_first_err: Option<Error> = None;
 
@@ -851,7 +1171,11 @@ impl LedgerStorage {
                     }
                 }
                 Ok(Ok(task_result)) => match task_result {
-                    TaskResult::BytesWritten(bytes) => _bytes_written += bytes,
+                    TaskResult::RowsWritten {
+                        table_name,
+                        rows,
+                        bytes,
+                    } => report.record_write(&table_name, rows, bytes),
                     TaskResult::CachedTransactions(count) => total_cached_transactions += count,
                 },
             }
@@ -881,19 +1205,33 @@ impl LedgerStorage {
             tx_by_addr_count,
             entries_count,
         };
+        let block_completeness = IngestorBlockCompleteness {
+            missing_meta_count,
+            synthesized_meta_count,
+            transactions_omitted,
+        };
+
+        if transactions_omitted {
+            warn!("HBase: block from slot {slot} is stored without its transactions");
+        } else if !block_completeness.is_complete() {
+            warn!(
+                "HBase: block from slot {slot} has {missing_meta_count} transactions without \
+                 metadata and {synthesized_meta_count} with synthesized metadata"
+            );
+        }
 
         // Store the block itself last, after all other metadata about the block has been
         // successfully stored.  This avoids partial uploaded blocks from becoming visible to
         // `get_confirmed_block()` and `get_confirmed_blocks()`
         let blocks_cells = [(
             slot_to_blocks_key(slot, self.uploader_config.use_md5_row_key_salt),
-            confirmed_block.into(),
+            confirmed_block_to_proto(confirmed_block),
         )];
 
         debug!("HBase: calling put_protobuf_cells_with_retry for blocks");
 
         if !self.uploader_config.disable_blocks {
-            _bytes_written += self
+            let bytes = self
                 .connection
                 .put_protobuf_cells_with_retry::<generated::ConfirmedBlock>(
                     self.uploader_config.blocks_table_name.as_str(),
@@ -906,28 +1244,152 @@ impl LedgerStorage {
                     error!("HBase: failed to upload block: {:?}", err);
                     err
                 })?;
+            report.record_write(&self.uploader_config.blocks_table_name, 1, bytes);
         }
 
         // Mark this block as fully uploaded
         if !self.uploader_config.disable_indexing_progress {
             let conn = self.connection.clone();
             let indexing_progress_table_name = self.uploader_config.indexing_progress_table_name.clone();
+
+            // Written for every block, so that a complete re-upload clears an earlier partial one
+            let completeness_cell = (slot_to_key(slot), block_completeness);
+            let completeness_bytes = conn
+                .put_bincode_column_cells_with_retry::<IngestorBlockCompleteness>(
+                    indexing_progress_table_name.as_str(),
+                    BLOCK_COMPLETENESS_COLUMN,
+                    &[completeness_cell],
+                    false,
+                    true,
+                )
+                .await
+                .inspect_err(|err| {
+                    error!("HBase: failed to upload block completeness: {err:?}");
+                })?;
+
             let indexing_progress_cell = (slot_to_key(slot), ingestor_indexing_progress);
-            conn.put_bincode_cells_with_retry::<IngestorIndexingProgress>(
-                indexing_progress_table_name.as_str(),
-                &[indexing_progress_cell],
-                false,
-                true,
+            let bytes = conn
+                .put_bincode_cells_with_retry::<IngestorIndexingProgress>(
+                    indexing_progress_table_name.as_str(),
+                    &[indexing_progress_cell],
+                    false,
+                    true,
+                )
+                .await
+                .inspect_err(|err| {
+                    error!("HBase: failed to upload indexing progress: {:?}", err);
+                })?;
+            report.record_write(&indexing_progress_table_name, 1, bytes + completeness_bytes);
+        }
+
+        if dry_run {
+            info!("HBase: dry run of block from slot {slot} complete");
+        } else {
+            info!("HBase: successfully uploaded block from slot {}", slot);
+        }
+
+        Ok(report)
+    }
+
+    /// Blockhash stored in indexing progress for the parent slot of a block, if the parent chain
+    /// check is on and it differs from the block's previous blockhash.
+    async fn find_parent_chain_mismatch(
+        &self,
+        slot: Slot,
+        confirmed_block: &ConfirmedBlock,
+    ) -> Result<Option<String>> {
+        if self.uploader_config.parent_chain_policy == ParentChainPolicy::Off
+            || confirmed_block.parent_slot >= slot
+        {
+            return Ok(None);
+        }
+        let parent = self
+            .connection
+            .get_bincode_cell_with_retry::<IngestorIndexingProgress>(
+                self.uploader_config.indexing_progress_table_name.as_str(),
+                &slot_to_key(confirmed_block.parent_slot),
+            )
+            .await;
+        // A parent row that does not decode says nothing about forks, so it does not fail the block
+        let parent = match parent {
+            Err(err @ (HBaseError::Io(_) | HBaseError::Bincode(_))) => {
+                warn!(
+                    "HBase: indexing progress for parent slot {} of slot {slot} does not decode, \
+                     parent chain not checked: {err}",
+                    confirmed_block.parent_slot
+                );
+                return Ok(None);
+            }
+            parent => parent?,
+        };
+        match parent {
+            Some(parent) if parent.blockhash != confirmed_block.previous_blockhash => {
+                Ok(Some(parent.blockhash))
+            }
+            Some(_) => Ok(None),
+            None => {
+                debug!(
+                    "HBase: no indexing progress for parent slot {} of slot {slot}, \
+                     parent chain not checked",
+                    confirmed_block.parent_slot
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Store a block in the quarantine table only, keyed by slot and blockhash so that blocks
+    /// from several forks of a slot are all kept.
+    pub async fn quarantine_block(
+        &self,
+        slot: Slot,
+        confirmed_block: ConfirmedBlock,
+    ) -> Result<UploadReport> {
+        let mut report = UploadReport {
+            slot,
+            ..UploadReport::default()
+        };
+        let quarantine_cells = [(
+            format!("{}/{}", slot_to_key(slot), confirmed_block.blockhash),
+            confirmed_block_to_proto(confirmed_block),
+        )];
+        let bytes = self
+            .connection
+            .put_protobuf_cells_with_retry(
+                self.uploader_config.quarantine_table_name.as_str(),
+                &quarantine_cells,
+                self.uploader_config.use_blocks_compression,
+                self.uploader_config.hbase_write_to_wal,
             )
             .await
             .inspect_err(|err| {
-                error!("HBase: failed to upload indexing progress: {:?}", err);
+                error!("HBase: failed to quarantine block: {err:?}");
             })?;
-        }
-
-        info!("HBase: successfully uploaded block from slot {}", slot);
+        report.record_write(&self.uploader_config.quarantine_table_name, 1, bytes);
+        report.quarantined = true;
+        info!("HBase: quarantined block from slot {slot}");
+        Ok(report)
+    }
 
-        Ok(())
+    /// Name of the first enabled filter that drops a voting or failed transaction from a table:
+    /// the table's own voting or error filter, or the matching filter for all tables.
+    fn transaction_filter(
+        &self,
+        is_voting: bool,
+        is_error: bool,
+        table_voting_filter: (&'static str, bool),
+        table_error_filter: (&'static str, bool),
+    ) -> &'static str {
+        let config = &self.uploader_config;
+        [
+            (is_voting, table_voting_filter),
+            (is_voting, ("filter_all_voting", config.filter_all_voting)),
+            (is_error, table_error_filter),
+            (is_error, ("filter_all_error", config.filter_all_error)),
+        ]
+        .into_iter()
+        .find_map(|(applies, (filter, enabled))| (applies && enabled).then_some(filter))
+        .unwrap_or("unknown")
     }
 
     fn should_include_in_tx_full(&self, address: &Pubkey) -> bool {
@@ -1119,6 +1581,32 @@ fn is_readonly_account(
     }
 }
 
+/// Unlike `VersionedConfirmedBlock`, a `ConfirmedBlock` has no protobuf conversion upstream.
+#[allow(deprecated)]
+fn confirmed_block_to_proto(confirmed_block: ConfirmedBlock) -> generated::ConfirmedBlock {
+    let ConfirmedBlock {
+        previous_blockhash,
+        blockhash,
+        parent_slot,
+        transactions,
+        rewards,
+        num_partitions,
+        block_time,
+        block_height,
+    } = confirmed_block;
+
+    generated::ConfirmedBlock {
+        previous_blockhash,
+        blockhash,
+        parent_slot,
+        transactions: transactions.into_iter().map(Into::into).collect(),
+        rewards: rewards.into_iter().map(Into::into).collect(),
+        num_partitions: num_partitions.map(Into::into),
+        block_time: block_time.map(|timestamp| generated::UnixTimestamp { timestamp }),
+        block_height: block_height.map(|block_height| generated::BlockHeight { block_height }),
+    }
+}
+
 pub(crate) fn convert_to_transaction_with_status_meta(
     item: VersionedTransactionWithStatusMeta,
 ) -> TransactionWithStatusMeta {
@@ -1,19 +1,31 @@
 // Re-export common modules for use by binaries
+pub mod block_envelope;
 pub mod block_processor;
+pub mod block_sanity;
+pub mod checksum;
 pub mod cli;
 pub mod config;
 pub mod decompressor;
 pub mod entries_parser;
+pub mod file_lifecycle;
 pub mod file_processor;
+pub mod file_registry;
+pub mod file_splitter;
 pub mod file_storage;
 pub mod format_parser;
+pub mod gap_detector;
 pub mod hbase;
 pub mod ingestor;
 pub mod ledger_storage;
 pub mod message_decoder;
+pub mod protobuf_decoder;
 pub mod queue_consumer;
 pub mod queue_producer;
 pub mod record_stream;
+pub mod schema_compat;
+pub mod schema_registry;
+pub mod signature_verification;
+pub mod transaction_encoding;
 pub mod json_utils;
 
 
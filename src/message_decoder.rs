@@ -3,17 +3,20 @@ use {
     serde_json::Value,
     // solana_block_decoder::transaction_status::EncodedConfirmedBlock,
     solana_block_decoder::block::encoded_block::EncodedConfirmedBlock,
-    solana_transaction_status::EntrySummary,
-    std::str,
+    solana_transaction_status::{EntrySummary, VersionedConfirmedBlockWithEntries},
+    std::{borrow::Cow, collections::HashMap, str, sync::Arc},
 };
-use crate::entries_parser::parse_entries_from_value;
-use crate::json_utils::from_value_with_path;
+use crate::block_envelope::{decode_envelope_str, BatchBlock, EnvelopePayload};
+use crate::checksum::ExpectedChecksum;
+use crate::decompressor::{decompress_bytes, Compression};
+use crate::queue_consumer::MessageHeaders;
+use crate::schema_compat::SchemaCompat;
 
 #[async_trait::async_trait]
 pub trait MessageDecoder: Send + Sync {
-    /// Decode a raw message into a `DecodedPayload`.
+    /// Decode a raw message into a `DecodedPayload`, using its headers where relevant.
     /// Return an error if it’s invalid or unrecognized.
-    async fn decode(&self, data: &[u8]) -> Result<DecodedPayload>;
+    async fn decode(&self, data: &[u8], headers: &MessageHeaders) -> Result<DecodedPayload>;
 }
 
 /// Represents what the raw payload actually decodes into.
@@ -21,99 +24,172 @@ pub enum DecodedPayload {
     /// A file path that should be processed by `Processor::process_file`.
     FilePath(String),
 
+    /// A file path plus the checksum its raw contents must match.
+    FilePathWithChecksum(String, ExpectedChecksum),
+
+    /// A file path plus a byte range (offset, length) of an uncompressed NDJSON file.
+    FileSplit(String, u64, u64),
+
+    /// A pointer to a single block record stored inside a file.
+    FileRange(FileRangeRef),
+
     /// A block ID plus the block data that should be uploaded to the storage.
     Block(u64, EncodedConfirmedBlock),
 
     /// A block ID with block data and entries summaries together.
     BlockWithEntries(u64, EncodedConfirmedBlock, Vec<EntrySummary>),
+
+    /// A block ID with a block that is already in its stored form and needs no conversion.
+    VersionedBlock(u64, VersionedConfirmedBlockWithEntries),
+
+    /// Several blocks carried by one message, each decoded on its own.
+    Blocks(Vec<BatchBlock>),
+}
+
+/// Location of a single, optionally compressed, block record inside a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRangeRef {
+    pub path: String,
+    pub offset: u64,
+    pub length: u64,
+    pub compression: Compression,
+    /// Slot the record is expected to contain, if known.
+    pub slot: Option<u64>,
+}
+
+/// Default upper bound on the decompressed size of a compressed message payload.
+pub const DEFAULT_MAX_DECOMPRESSED_MESSAGE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Header naming the codec of a compressed payload, e.g. `zstd` or `gzip`.
+pub const CONTENT_ENCODING_HEADER: &str = "content-encoding";
+
+/// Decompress a gzip or zstd payload, taking the codec from the `content-encoding` header if
+/// present and from magic bytes otherwise. Uncompressed payloads are returned as is.
+pub async fn decompress_payload<'a>(
+    data: &'a [u8],
+    headers: &MessageHeaders,
+    max_decompressed_size: u64,
+) -> Result<Cow<'a, [u8]>> {
+    let compression = match headers.get_str(CONTENT_ENCODING_HEADER) {
+        Some(encoding) => encoding
+            .parse::<Compression>()
+            .context("Invalid content-encoding header")?,
+        None => Compression::detect(data),
+    };
+    if compression == Compression::None {
+        return Ok(Cow::Borrowed(data));
+    }
+    Ok(Cow::Owned(
+        decompress_bytes(compression, data, max_decompressed_size).await?,
+    ))
 }
 
-pub struct JsonMessageDecoder;
+/// Decodes JSON payloads, optionally gzip or zstd compressed. The codec is taken from the
+/// `content-encoding` header if present, and detected from magic bytes otherwise.
+pub struct JsonMessageDecoder {
+    max_decompressed_size: u64,
+    schema_compat: SchemaCompat,
+}
+
+impl JsonMessageDecoder {
+    pub fn new(max_decompressed_size: u64) -> Self {
+        Self {
+            max_decompressed_size,
+            schema_compat: SchemaCompat::default(),
+        }
+    }
+
+    /// Handle schema drift in blocks with the given settings.
+    pub fn with_schema_compat(mut self, schema_compat: SchemaCompat) -> Self {
+        self.schema_compat = schema_compat;
+        self
+    }
+}
+
+impl Default for JsonMessageDecoder {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_DECOMPRESSED_MESSAGE_SIZE)
+    }
+}
 
 #[async_trait::async_trait]
 impl MessageDecoder for JsonMessageDecoder {
-    async fn decode(&self, data: &[u8]) -> Result<DecodedPayload> {
+    async fn decode(&self, data: &[u8], headers: &MessageHeaders) -> Result<DecodedPayload> {
+        let data = decompress_payload(data, headers, self.max_decompressed_size).await?;
+
         // Convert bytes to string
-        let msg_str =
-            str::from_utf8(data).map_err(|e| anyhow!("Invalid UTF-8 in message: {}", e))?;
+        let mut msg_string = String::from_utf8(data.into_owned())
+            .map_err(|e| anyhow!("Invalid UTF-8 in message: {}", e))?;
+
+        // Block envelopes and batches are decoded straight from the text, without building a
+        // `Value` for the block. They are shared with the file record parser.
+        match decode_envelope_str(&mut msg_string, &self.schema_compat)? {
+            Some(EnvelopePayload::Block(envelope)) => {
+                return Ok(DecodedPayload::BlockWithEntries(
+                    envelope.block_id,
+                    envelope.block,
+                    envelope.entries,
+                ));
+            }
+            Some(EnvelopePayload::Batch(blocks)) => return Ok(DecodedPayload::Blocks(blocks)),
+            None => {}
+        }
+        let msg_str = msg_string.as_str();
 
         // Attempt to parse as JSON
         match serde_json::from_str::<Value>(msg_str) {
             Ok(json_val) => {
-                // Support JSON-RPC wrapper: { "jsonrpc": "2.0", "result": { ... }, "id": n }
-                let json_val = if let Some(result) = json_val.get("result") {
-                    result.clone()
-                } else {
-                    json_val
-                };
-
-                // Preferred format: top-level block data with optional entries
-                if let Some(block_id) = json_val["blockID"].as_u64() {
-                    let entries = if let Some(entries_value) = json_val.get("entries") {
-                        parse_entries_from_value(entries_value).with_context(|| {
-                            format!("Failed to parse entries field - slot={block_id}")
-                        })?
-                    } else {
-                        vec![]
-                    };
-
-                    // Remove non-block fields before parsing into EncodedConfirmedBlock
-                    let block_value = if let Some(mut obj) = json_val.as_object().cloned() {
-                        let _ = obj.remove("entries");
-                        let _ = obj.remove("blockID");
-                        Value::Object(obj)
-                    } else {
-                        json_val.clone()
-                    };
-
-                    let block: EncodedConfirmedBlock = from_value_with_path(block_value, "EncodedConfirmedBlock")
-                        .with_context(|| {
-                            format!("Failed to parse EncodedConfirmedBlock - slot={block_id}")
+                // Alternatively, JSON may be a file path wrapper, optionally with a checksum
+                // formatted as "<algorithm>:<hex>"
+                if let Some(file_path) = json_val["hdfs_path"].as_str() {
+                    if let (Some(offset), Some(length)) =
+                        (json_val["offset"].as_u64(), json_val["length"].as_u64())
+                    {
+                        return Ok(DecodedPayload::FileSplit(
+                            file_path.to_string(),
+                            offset,
+                            length,
+                        ));
+                    }
+                    if let Some(checksum) = json_val["checksum"].as_str() {
+                        let checksum = checksum.parse::<ExpectedChecksum>().with_context(|| {
+                            format!("Invalid checksum for file path payload: {file_path}")
                         })?;
-
-                    return Ok(DecodedPayload::BlockWithEntries(block_id, block, entries));
+                        return Ok(DecodedPayload::FilePathWithChecksum(
+                            file_path.to_string(),
+                            checksum,
+                        ));
+                    }
+                    return Ok(DecodedPayload::FilePath(file_path.to_string()));
                 }
 
-                // Fallback format support for nested { block: {...}, entries: {...} }
-                if json_val.get("block").is_some() {
-                    let block_value = &json_val["block"];
-                    let block_id = block_value["blockID"]
-                        .as_u64()
-                        .ok_or_else(|| anyhow!("Missing block.blockID in payload"))?;
-                    // Remove blockID before parsing block
-                    let cleaned_block_value = if let Some(mut obj) = block_value.as_object().cloned() {
-                        let _ = obj.remove("blockID");
-                        Value::Object(obj)
-                    } else {
-                        block_value.clone()
-                    };
-                    let block: EncodedConfirmedBlock = from_value_with_path(cleaned_block_value, "EncodedConfirmedBlock")
-                        .with_context(|| {
-                            format!("Failed to parse EncodedConfirmedBlock from block field - slot={block_id}")
-                        })?;
-
-                    let entries = if let Some(entries_value) = json_val.get("entries") {
-                        parse_entries_from_value(entries_value).with_context(|| {
-                            format!("Failed to parse entries field - slot={block_id}")
-                        })?
-                    } else {
-                        vec![]
+                // Claim-check pointer to a single record:
+                // { "path": "hdfs://...", "offset": N, "length": M, "compression": "zstd", "slot": S }
+                if let (Some(path), Some(offset), Some(length)) = (
+                    json_val["path"].as_str(),
+                    json_val["offset"].as_u64(),
+                    json_val["length"].as_u64(),
+                ) {
+                    let compression = match json_val["compression"].as_str() {
+                        Some(compression) => compression.parse::<Compression>()?,
+                        None => Compression::None,
                     };
-                    return Ok(DecodedPayload::BlockWithEntries(block_id, block, entries));
-                }
-
-                // Alternatively, JSON may be a file path wrapper
-                if let Some(file_path) = json_val["hdfs_path"].as_str() {
-                    return Ok(DecodedPayload::FilePath(file_path.to_string()));
+                    return Ok(DecodedPayload::FileRange(FileRangeRef {
+                        path: path.to_string(),
+                        offset,
+                        length,
+                        compression,
+                        slot: json_val["slot"].as_u64(),
+                    }));
                 }
 
                 Err(anyhow!("Unrecognized JSON payload: {}", msg_str))
             }
             Err(_) => {
-                // If it fails to parse as JSON, maybe the entire string is a file path
-                // e.g. "hdfs://my-file.gz"
+                // If it fails to parse as JSON, the entire string may be a file path,
+                // e.g. "hdfs://namenode/blocks/my-file.gz"
                 let trimmed = msg_str.trim();
-                if trimmed.ends_with(".gz") || trimmed.contains("hdfs://") {
+                if looks_like_file_path(trimmed) {
                     Ok(DecodedPayload::FilePath(trimmed.to_string()))
                 } else {
                     Err(anyhow!(
@@ -125,3 +201,75 @@ impl MessageDecoder for JsonMessageDecoder {
         }
     }
 }
+
+/// Whether a plain-text payload is a plausible file path: a single token that is either an
+/// `hdfs://` URI or an absolute path.
+pub fn looks_like_file_path(s: &str) -> bool {
+    let is_single_token = !s.is_empty() && !s.chars().any(|c| c.is_whitespace() || c.is_control());
+    is_single_token && (s.starts_with("hdfs://") || s.starts_with('/'))
+}
+
+/// Decodes a plain-text payload as a single file path, for messages explicitly marked as such.
+pub struct FilePathDecoder;
+
+#[async_trait::async_trait]
+impl MessageDecoder for FilePathDecoder {
+    async fn decode(&self, data: &[u8], _headers: &MessageHeaders) -> Result<DecodedPayload> {
+        let path = str::from_utf8(data)
+            .map_err(|e| anyhow!("Invalid UTF-8 in file path message: {}", e))?
+            .trim();
+        if path.is_empty() || path.chars().any(|c| c.is_control()) {
+            return Err(anyhow!("Invalid file path message: {:?}", path));
+        }
+        Ok(DecodedPayload::FilePath(path.to_string()))
+    }
+}
+
+/// Header selecting the decoder of a message, e.g. `json`, `file-path` or `protobuf-block`.
+pub const PAYLOAD_TYPE_HEADER: &str = "payload-type";
+
+/// Selects a decoder by the `payload-type` header of each message. Messages without the header
+/// are decoded by the fallback decoder, and unknown payload types are rejected.
+pub struct RoutingMessageDecoder {
+    decoders: HashMap<String, Arc<dyn MessageDecoder + Send + Sync>>,
+    fallback: Arc<dyn MessageDecoder + Send + Sync>,
+}
+
+impl RoutingMessageDecoder {
+    pub fn new(fallback: Arc<dyn MessageDecoder + Send + Sync>) -> Self {
+        Self {
+            decoders: HashMap::new(),
+            fallback,
+        }
+    }
+
+    /// Register a decoder for a payload type. Payload types are matched case-insensitively.
+    pub fn with_decoder(
+        mut self,
+        payload_type: &str,
+        decoder: Arc<dyn MessageDecoder + Send + Sync>,
+    ) -> Self {
+        self.decoders.insert(payload_type.to_ascii_lowercase(), decoder);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl MessageDecoder for RoutingMessageDecoder {
+    async fn decode(&self, data: &[u8], headers: &MessageHeaders) -> Result<DecodedPayload> {
+        let Some(payload_type) = headers.get(PAYLOAD_TYPE_HEADER) else {
+            return self.fallback.decode(data, headers).await;
+        };
+        let payload_type = str::from_utf8(payload_type)
+            .map(|t| t.trim().to_ascii_lowercase())
+            .map_err(|_| anyhow!("Invalid UTF-8 in {PAYLOAD_TYPE_HEADER} header"))?;
+        let decoder = self
+            .decoders
+            .get(&payload_type)
+            .ok_or_else(|| anyhow!("Unknown {PAYLOAD_TYPE_HEADER}: {payload_type}"))?;
+        decoder
+            .decode(data, headers)
+            .await
+            .with_context(|| format!("Failed to decode {payload_type} payload"))
+    }
+}
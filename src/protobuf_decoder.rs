@@ -0,0 +1,195 @@
+use {
+    crate::{
+        message_decoder::{
+            decompress_payload, DecodedPayload, MessageDecoder,
+            DEFAULT_MAX_DECOMPRESSED_MESSAGE_SIZE,
+        },
+        queue_consumer::MessageHeaders,
+    },
+    anyhow::{anyhow, Context, Result},
+    dexter_storage_proto_tx::convert::generated,
+    prost::Message,
+    solana_transaction_status::{
+        EntrySummary, VersionedConfirmedBlock, VersionedConfirmedBlockWithEntries,
+        VersionedTransactionWithStatusMeta,
+    },
+};
+
+// The generated protobuf types are flagged as unstable API by the solana crates, so the items
+// using them allow deprecated uses one by one.
+#[allow(deprecated)]
+use solana_storage_proto::convert::entries;
+
+/// Header holding the slot of a protobuf block message, as a decimal string.
+pub const SLOT_HEADER: &str = "slot";
+
+const SIGNATURE_BYTES: usize = 64;
+const PUBKEY_BYTES: usize = 32;
+const HASH_BYTES: usize = 32;
+
+/// Entries travel in field 100 of the block message. Decoders of a plain `ConfirmedBlock`
+/// skip the field, so the same message stays readable by them.
+#[allow(deprecated)]
+#[derive(Clone, PartialEq, Message)]
+struct BlockEntries {
+    #[prost(message, repeated, tag = "100")]
+    entries: Vec<entries::Entry>,
+}
+
+/// Decodes `ConfirmedBlock` protobuf messages keyed by a `slot` header. The block is converted
+/// directly into its stored form, without going through `convert_block`.
+pub struct ProtobufBlockDecoder {
+    max_decompressed_size: u64,
+}
+
+impl ProtobufBlockDecoder {
+    pub fn new(max_decompressed_size: u64) -> Self {
+        Self {
+            max_decompressed_size,
+        }
+    }
+}
+
+impl Default for ProtobufBlockDecoder {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_DECOMPRESSED_MESSAGE_SIZE)
+    }
+}
+
+#[async_trait::async_trait]
+impl MessageDecoder for ProtobufBlockDecoder {
+    async fn decode(&self, data: &[u8], headers: &MessageHeaders) -> Result<DecodedPayload> {
+        let block_id = headers
+            .get_str(SLOT_HEADER)
+            .ok_or_else(|| anyhow!("Missing {SLOT_HEADER} header for protobuf block message"))?
+            .trim()
+            .parse::<u64>()
+            .context("Invalid slot header")?;
+
+        let data = decompress_payload(data, headers, self.max_decompressed_size).await?;
+        let data = data.as_ref();
+
+        let block = decode_confirmed_block(block_id, data)?;
+
+        let entries = BlockEntries::decode(data)
+            .with_context(|| format!("Failed to decode entries protobuf - slot={block_id}"))?;
+        let entries = convert_entries(entries.entries)
+            .with_context(|| format!("Invalid entries protobuf - slot={block_id}"))?;
+
+        Ok(DecodedPayload::VersionedBlock(
+            block_id,
+            VersionedConfirmedBlockWithEntries { block, entries },
+        ))
+    }
+}
+
+#[allow(deprecated)]
+fn decode_confirmed_block(block_id: u64, data: &[u8]) -> Result<VersionedConfirmedBlock> {
+    let block = generated::ConfirmedBlock::decode(data)
+        .with_context(|| format!("Failed to decode ConfirmedBlock protobuf - slot={block_id}"))?;
+    convert_confirmed_block(block)
+        .with_context(|| format!("Invalid ConfirmedBlock protobuf - slot={block_id}"))
+}
+
+#[allow(deprecated)]
+fn convert_confirmed_block(block: generated::ConfirmedBlock) -> Result<VersionedConfirmedBlock> {
+    let generated::ConfirmedBlock {
+        previous_blockhash,
+        blockhash,
+        parent_slot,
+        transactions,
+        rewards,
+        block_time,
+        block_height,
+        num_partitions,
+    } = block;
+
+    let transactions = transactions
+        .into_iter()
+        .enumerate()
+        .map(|(index, tx)| {
+            convert_transaction(tx).with_context(|| format!("transaction index: {index}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(VersionedConfirmedBlock {
+        previous_blockhash,
+        blockhash,
+        parent_slot,
+        transactions,
+        rewards: rewards.into_iter().map(Into::into).collect(),
+        num_partitions: num_partitions.map(|n| n.num_partitions),
+        block_time: block_time.map(|t| t.timestamp),
+        block_height: block_height.map(|h| h.block_height),
+    })
+}
+
+/// The upstream conversions panic on malformed input, so the fields they unwrap are checked first.
+#[allow(deprecated)]
+fn convert_transaction(
+    tx: generated::ConfirmedTransaction,
+) -> Result<VersionedTransactionWithStatusMeta> {
+    let transaction = tx
+        .transaction
+        .ok_or_else(|| anyhow!("Missing transaction"))?;
+    let meta = tx
+        .meta
+        .ok_or_else(|| anyhow!("Missing transaction status meta"))?;
+
+    if let Some(signature) = transaction
+        .signatures
+        .iter()
+        .find(|s| s.len() != SIGNATURE_BYTES)
+    {
+        return Err(anyhow!("Invalid signature length: {}", signature.len()));
+    }
+    let message = transaction
+        .message
+        .as_ref()
+        .ok_or_else(|| anyhow!("Missing transaction message"))?;
+    if message.header.is_none() {
+        return Err(anyhow!("Missing message header"));
+    }
+    if message.recent_blockhash.len() != HASH_BYTES {
+        return Err(anyhow!(
+            "Invalid recent blockhash length: {}",
+            message.recent_blockhash.len()
+        ));
+    }
+    let account_keys = message.account_keys.iter();
+    let lookup_keys = message
+        .address_table_lookups
+        .iter()
+        .map(|lookup| &lookup.account_key);
+    let return_data_keys = meta.return_data.iter().map(|data| &data.program_id);
+    if let Some(key) = account_keys
+        .chain(lookup_keys)
+        .chain(return_data_keys)
+        .find(|key| key.len() != PUBKEY_BYTES)
+    {
+        return Err(anyhow!("Invalid public key length: {}", key.len()));
+    }
+
+    Ok(VersionedTransactionWithStatusMeta {
+        transaction: transaction.into(),
+        meta: meta.try_into()?,
+    })
+}
+
+#[allow(deprecated)]
+fn convert_entries(mut entries: Vec<entries::Entry>) -> Result<Vec<EntrySummary>> {
+    entries.sort_by_key(|entry| entry.index);
+    entries
+        .into_iter()
+        .map(|entry| {
+            if entry.hash.len() != HASH_BYTES {
+                return Err(anyhow!(
+                    "Invalid entry hash length at index {}: {}",
+                    entry.index,
+                    entry.hash.len()
+                ));
+            }
+            Ok(entry.into())
+        })
+        .collect()
+}
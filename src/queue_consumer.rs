@@ -1,31 +1,77 @@
 use {
     anyhow::Result,
     async_trait::async_trait,
+    bytes::Bytes,
     rdkafka::{
         config::{ClientConfig, RDKafkaLogLevel},
         consumer::{CommitMode, Consumer, StreamConsumer},
+        message::Headers,
         Message as RDKafkaMessage,
     },
+    std::str,
 };
 
-pub struct QueueMessage<T> {
-    internal: T,
+/// Headers attached to a queue message, in the order they were sent.
+#[derive(Debug, Clone, Default)]
+pub struct MessageHeaders {
+    headers: Vec<(String, Option<Bytes>)>,
 }
 
-impl<T> QueueMessage<T> {
-    pub fn new(internal: T) -> Self {
-        Self { internal }
+impl MessageHeaders {
+    pub fn new(headers: Vec<(String, Option<Bytes>)>) -> Self {
+        Self { headers }
     }
 
-    pub fn internal(&self) -> &T {
-        &self.internal
+    /// Value of the first header with the given name, compared case-insensitively.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// Like `get`, but only returns values that are valid UTF-8.
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(|value| str::from_utf8(value).ok())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&[u8]>)> {
+        self.headers
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_deref()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+}
+
+/// A raw message received from the queue together with its metadata.
+#[derive(Debug, Clone, Default)]
+pub struct QueueMessage {
+    pub payload: Bytes,
+    pub key: Option<Bytes>,
+    pub headers: MessageHeaders,
+    /// Message timestamp in milliseconds since the Unix epoch, if the broker provided one.
+    pub timestamp: Option<i64>,
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+}
+
+impl QueueMessage {
+    pub fn new(payload: Bytes) -> Self {
+        Self {
+            payload,
+            ..Default::default()
+        }
     }
 }
 
 #[async_trait]
 pub trait QueueConsumer: Send + Sync {
-    async fn next_message(&mut self) -> Option<Result<QueueMessage<String>>>;
-    async fn commit(&self, message: &QueueMessage<String>) -> Result<()>;
+    async fn next_message(&mut self) -> Option<Result<QueueMessage>>;
+    async fn commit(&self, message: &QueueMessage) -> Result<()>;
 }
 
 // Blanket implementation for Box<dyn QueueConsumer + Send + Sync>
@@ -35,11 +81,11 @@ impl<T> QueueConsumer for Box<T>
 where
     T: QueueConsumer + Send + Sync + ?Sized,
 {
-    async fn next_message(&mut self) -> Option<Result<QueueMessage<String>>> {
+    async fn next_message(&mut self) -> Option<Result<QueueMessage>> {
         T::next_message(self).await
     }
 
-    async fn commit(&self, message: &QueueMessage<String>) -> Result<()> {
+    async fn commit(&self, message: &QueueMessage) -> Result<()> {
         T::commit(self, message).await
     }
 }
@@ -112,23 +158,40 @@ impl KafkaQueueConsumer {
 
 #[async_trait]
 impl QueueConsumer for KafkaQueueConsumer {
-    /// Fetches the next message from Kafka, converting its payload into a `String`.
-    async fn next_message(&mut self) -> Option<Result<QueueMessage<String>>> {
+    /// Fetches the next message from Kafka, keeping its payload as raw bytes.
+    async fn next_message(&mut self) -> Option<Result<QueueMessage>> {
         match self.kafka_consumer.recv().await {
             Ok(msg) => {
-                // Convert the payload bytes into a String
-                let payload_str = match msg.payload() {
-                    Some(bytes) => String::from_utf8_lossy(bytes).to_string(),
-                    None => String::new(),
-                };
-                Some(Ok(QueueMessage::new(payload_str)))
+                let headers = msg
+                    .headers()
+                    .map(|headers| {
+                        headers
+                            .iter()
+                            .map(|header| {
+                                (
+                                    header.key.to_string(),
+                                    header.value.map(Bytes::copy_from_slice),
+                                )
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Some(Ok(QueueMessage {
+                    payload: msg.payload().map(Bytes::copy_from_slice).unwrap_or_default(),
+                    key: msg.key().map(Bytes::copy_from_slice),
+                    headers: MessageHeaders::new(headers),
+                    timestamp: msg.timestamp().to_millis(),
+                    topic: msg.topic().to_string(),
+                    partition: msg.partition(),
+                    offset: msg.offset(),
+                }))
             }
             Err(e) => Some(Err(anyhow::anyhow!("Kafka error: {}", e))),
         }
     }
 
     /// Commits the consumer state asynchronously.
-    async fn commit(&self, _message: &QueueMessage<String>) -> Result<()> {
+    async fn commit(&self, _message: &QueueMessage) -> Result<()> {
         self.kafka_consumer
             .commit_consumer_state(CommitMode::Async)
             .map_err(|e| anyhow::anyhow!("Commit error: {:?}", e))
@@ -36,3 +36,113 @@ impl RecordStream for NdJsonRecordStream {
         }
     }
 }
+
+/// A line-based NDJSON record stream over a byte range of a file.
+///
+/// The input must start at byte `start` of the file and may run past `end`. Unless the range
+/// starts at the beginning of the file, everything up to and including the first newline is
+/// skipped, since that record belongs to the previous range. Records are returned as long as
+/// they start at or before `end`, so the record straddling `end` is read in full.
+pub struct RangeRecordStream {
+    reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>,
+    position: u64,
+    end: u64,
+    aligned: bool,
+}
+
+impl RangeRecordStream {
+    pub fn new(input: Box<dyn AsyncRead + Unpin + Send>, start: u64, end: u64) -> Self {
+        Self {
+            reader: BufReader::new(input),
+            position: start,
+            end,
+            aligned: start == 0,
+        }
+    }
+}
+
+#[async_trait]
+impl RecordStream for RangeRecordStream {
+    async fn next_record(&mut self) -> Option<Result<String>> {
+        if !self.aligned {
+            let mut skipped = vec![];
+            match self.reader.read_until(b'\n', &mut skipped).await {
+                Ok(n) => self.position += n as u64,
+                Err(e) => return Some(Err(e.into())),
+            }
+            self.aligned = true;
+        }
+
+        if self.position > self.end {
+            return None;
+        }
+
+        let mut line = String::new();
+        match self.reader.read_line(&mut line).await {
+            Ok(0) => None, // EOF
+            Ok(n) => {
+                self.position += n as u64;
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::file_splitter::split_ranges, std::io::Cursor};
+
+    /// Read every range of `data` the way `process_file_range` does, returning all records.
+    async fn read_ranges(data: &[u8], num_splits: u64) -> Vec<String> {
+        let mut records = vec![];
+        for (offset, length) in split_ranges(data.len() as u64, num_splits) {
+            let input = Cursor::new(data[offset as usize..].to_vec());
+            let mut stream = RangeRecordStream::new(Box::new(input), offset, offset + length);
+            while let Some(record) = stream.next_record().await {
+                records.push(record.unwrap());
+            }
+        }
+        records
+    }
+
+    fn lines(data: &str) -> Vec<String> {
+        data.split_inclusive('\n').map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn test_ranges_read_every_record_once() {
+        let data = "{\"blockID\":1}\n{\"blockID\":22}\n{\"blockID\":333}\n{\"blockID\":4444}\n\
+                    {\"blockID\":55555}\n";
+        for num_splits in 1..=data.len() as u64 {
+            assert_eq!(
+                read_ranges(data.as_bytes(), num_splits).await,
+                lines(data),
+                "num_splits: {num_splits}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_ending_on_a_range_boundary() {
+        // Five 10-byte records split into five ranges, so every range ends with a newline
+        let data = "{\"a\":111}\n{\"a\":222}\n{\"a\":333}\n{\"a\":444}\n{\"a\":555}\n";
+        assert_eq!(
+            split_ranges(data.len() as u64, 5),
+            vec![(0, 10), (10, 10), (20, 10), (30, 10), (40, 10)]
+        );
+        assert_eq!(read_ranges(data.as_bytes(), 5).await, lines(data));
+    }
+
+    #[tokio::test]
+    async fn test_last_record_without_newline() {
+        let data = "{\"a\":1}\n{\"a\":2}\n{\"a\":3}";
+        for num_splits in 1..=data.len() as u64 {
+            assert_eq!(
+                read_ranges(data.as_bytes(), num_splits).await,
+                lines(data),
+                "num_splits: {num_splits}"
+            );
+        }
+    }
+}
@@ -0,0 +1,598 @@
+use {
+    anyhow::{anyhow, Result},
+    log::warn,
+    serde_json::{Map, Value},
+    std::{
+        fmt,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+    },
+};
+
+/// How block JSON that drifted from the expected schema is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaCompatMode {
+    /// Decode blocks as they are.
+    Off,
+    /// Apply the compatibility rules to blocks that fail to decode as they are, or that have keys
+    /// decoding would ignore, and retry.
+    #[default]
+    Lenient,
+    /// Fail every block that one of the rules would have to change.
+    Strict,
+}
+
+impl FromStr for SchemaCompatMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(SchemaCompatMode::Off),
+            "lenient" => Ok(SchemaCompatMode::Lenient),
+            "strict" => Ok(SchemaCompatMode::Strict),
+            other => Err(anyhow!("Unsupported schema compatibility mode: {other}")),
+        }
+    }
+}
+
+/// A normalisation rule. Rules look at the block object, its rewards, its transactions and
+/// their status metadata; messages and instructions are left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompatRule {
+    /// Rename snake_case keys, e.g. `parent_slot`, to the camelCase key of a known field.
+    RenameLegacyKeys,
+    /// Remove keys that are not fields of the object, e.g. fields added by newer RPC nodes.
+    DropUnknownFields,
+    /// Turn integer fields given as strings, e.g. `"fee": "5000"`, into numbers.
+    CoerceNumericStrings,
+    /// Fill in fields that are null or missing: block `transactions` and `rewards`, which getBlock
+    /// omits with transactionDetails=none or rewards=false, and either of the transaction `err`
+    /// and `status`, which are derived from each other.
+    DefaultMissingFields,
+    /// Map reward types to the known ones ignoring case, and drop unknown reward types.
+    UnknownRewardTypes,
+}
+
+impl CompatRule {
+    pub const ALL: [CompatRule; 5] = [
+        CompatRule::RenameLegacyKeys,
+        CompatRule::DropUnknownFields,
+        CompatRule::CoerceNumericStrings,
+        CompatRule::DefaultMissingFields,
+        CompatRule::UnknownRewardTypes,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompatRule::RenameLegacyKeys => "rename_legacy_keys",
+            CompatRule::DropUnknownFields => "drop_unknown_fields",
+            CompatRule::CoerceNumericStrings => "coerce_numeric_strings",
+            CompatRule::DefaultMissingFields => "default_missing_fields",
+            CompatRule::UnknownRewardTypes => "unknown_reward_types",
+        }
+    }
+}
+
+impl fmt::Display for CompatRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// `blockID`, `entries` and `encoding` belong to the envelope but may sit next to block fields
+const BLOCK_FIELDS: &[&str] = &[
+    "previousBlockhash",
+    "blockhash",
+    "parentSlot",
+    "transactions",
+    "rewards",
+    "numPartitions",
+    "blockTime",
+    "blockHeight",
+    "blockID",
+    "entries",
+    "encoding",
+];
+const BLOCK_NUMERIC_FIELDS: &[&str] = &["parentSlot", "numPartitions", "blockTime", "blockHeight"];
+
+const TRANSACTION_FIELDS: &[&str] = &["transaction", "meta", "version"];
+
+const META_FIELDS: &[&str] = &[
+    "err",
+    "status",
+    "fee",
+    "preBalances",
+    "postBalances",
+    "innerInstructions",
+    "logMessages",
+    "preTokenBalances",
+    "postTokenBalances",
+    "rewards",
+    "loadedAddresses",
+    "returnData",
+    "computeUnitsConsumed",
+    "costUnits",
+];
+const META_NUMERIC_FIELDS: &[&str] = &["fee", "computeUnitsConsumed", "costUnits"];
+const META_NUMERIC_ARRAY_FIELDS: &[&str] = &["preBalances", "postBalances"];
+
+const REWARD_FIELDS: &[&str] = &[
+    "pubkey",
+    "lamports",
+    "postBalance",
+    "rewardType",
+    "commission",
+];
+const REWARD_NUMERIC_FIELDS: &[&str] = &["lamports", "postBalance", "commission"];
+const REWARD_TYPES: &[&str] = &["Fee", "Rent", "Staking", "Voting"];
+
+/// Schema compatibility settings, with counts of the rules applied so far. Clones share the
+/// counts.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaCompat {
+    mode: SchemaCompatMode,
+    counts: Arc<[AtomicU64; CompatRule::ALL.len()]>,
+}
+
+impl SchemaCompat {
+    pub fn new(mode: SchemaCompatMode) -> Self {
+        Self {
+            mode,
+            counts: Arc::default(),
+        }
+    }
+
+    pub fn mode(&self) -> SchemaCompatMode {
+        self.mode
+    }
+
+    /// How often each rule was applied since these settings were created.
+    pub fn rule_counts(&self) -> Vec<(CompatRule, u64)> {
+        CompatRule::ALL
+            .iter()
+            .map(|rule| (*rule, self.counts[*rule as usize].load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Apply the rules to a block object and return how many changes were made. In strict
+    /// mode the first change that would be needed is returned as an error instead.
+    pub fn normalize_block(&self, block: &mut Value, block_id: u64) -> Result<u64> {
+        let mut normalizer = Normalizer {
+            strict: self.mode == SchemaCompatMode::Strict,
+            counts: [0; CompatRule::ALL.len()],
+        };
+        normalizer.block(block)?;
+
+        let applied: u64 = normalizer.counts.iter().sum();
+        if applied > 0 {
+            let summary = CompatRule::ALL
+                .iter()
+                .filter(|rule| normalizer.counts[**rule as usize] > 0)
+                .map(|rule| format!("{rule}={}", normalizer.counts[*rule as usize]))
+                .collect::<Vec<_>>()
+                .join(", ");
+            warn!("Applied schema compatibility rules to block slot={block_id}: {summary}");
+            for (total, count) in self.counts.iter().zip(normalizer.counts) {
+                total.fetch_add(count, Ordering::Relaxed);
+            }
+        }
+        Ok(applied)
+    }
+}
+
+struct Normalizer {
+    strict: bool,
+    counts: [u64; CompatRule::ALL.len()],
+}
+
+impl Normalizer {
+    /// Record that `rule` changes the value at `path`, or fail in strict mode.
+    fn apply(&mut self, rule: CompatRule, path: &str, key: &str) -> Result<()> {
+        if self.strict {
+            return Err(anyhow!(
+                "Schema drift at `{}`: rule {rule} would apply (strict schema compatibility)",
+                join_path(path, key)
+            ));
+        }
+        self.counts[rule as usize] += 1;
+        Ok(())
+    }
+
+    fn block(&mut self, block: &mut Value) -> Result<()> {
+        let Value::Object(obj) = block else {
+            return Ok(());
+        };
+        self.fields(obj, "", BLOCK_FIELDS, BLOCK_NUMERIC_FIELDS)?;
+        for key in ["transactions", "rewards"] {
+            if matches!(obj.get(key), None | Some(Value::Null)) {
+                self.apply(CompatRule::DefaultMissingFields, "", key)?;
+                obj.insert(key.to_string(), Value::Array(vec![]));
+            }
+        }
+
+        if let Some(Value::Array(rewards)) = obj.get_mut("rewards") {
+            self.rewards(rewards, "rewards")?;
+        }
+        if let Some(Value::Array(transactions)) = obj.get_mut("transactions") {
+            for (index, transaction) in transactions.iter_mut().enumerate() {
+                if let Value::Object(transaction) = transaction {
+                    self.transaction(transaction, &format!("transactions[{index}]"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn transaction(&mut self, transaction: &mut Map<String, Value>, path: &str) -> Result<()> {
+        self.fields(transaction, path, TRANSACTION_FIELDS, &[])?;
+        let Some(Value::Object(meta)) = transaction.get_mut("meta") else {
+            return Ok(());
+        };
+        let path = join_path(path, "meta");
+        self.fields(meta, &path, META_FIELDS, META_NUMERIC_FIELDS)?;
+        for key in META_NUMERIC_ARRAY_FIELDS {
+            if let Some(Value::Array(values)) = meta.get_mut(*key) {
+                for (index, value) in values.iter_mut().enumerate() {
+                    self.coerce_number(value, &path, &format!("{key}[{index}]"))?;
+                }
+            }
+        }
+
+        // `status` is the deprecated form of `err`, and both are required
+        match (meta.contains_key("err"), meta.contains_key("status")) {
+            (true, false) => {
+                self.apply(CompatRule::DefaultMissingFields, &path, "status")?;
+                let status = match &meta["err"] {
+                    Value::Null => serde_json::json!({ "Ok": null }),
+                    err => serde_json::json!({ "Err": err }),
+                };
+                meta.insert("status".to_string(), status);
+            }
+            (false, true) => {
+                self.apply(CompatRule::DefaultMissingFields, &path, "err")?;
+                let err = meta["status"].get("Err").cloned().unwrap_or(Value::Null);
+                meta.insert("err".to_string(), err);
+            }
+            _ => {}
+        }
+
+        if let Some(Value::Array(rewards)) = meta.get_mut("rewards") {
+            self.rewards(rewards, &join_path(&path, "rewards"))?;
+        }
+        Ok(())
+    }
+
+    fn rewards(&mut self, rewards: &mut [Value], path: &str) -> Result<()> {
+        for (index, reward) in rewards.iter_mut().enumerate() {
+            let Value::Object(reward) = reward else {
+                continue;
+            };
+            let path = format!("{path}[{index}]");
+            self.fields(reward, &path, REWARD_FIELDS, REWARD_NUMERIC_FIELDS)?;
+
+            let Some(Value::String(reward_type)) = reward.get("rewardType") else {
+                continue;
+            };
+            if REWARD_TYPES.contains(&reward_type.as_str()) {
+                continue;
+            }
+            self.apply(CompatRule::UnknownRewardTypes, &path, "rewardType")?;
+            let known = REWARD_TYPES
+                .iter()
+                .find(|known| known.eq_ignore_ascii_case(reward_type));
+            let reward_type = match known {
+                Some(known) => Value::String(known.to_string()),
+                None => Value::Null,
+            };
+            reward.insert("rewardType".to_string(), reward_type);
+        }
+        Ok(())
+    }
+
+    /// Rename legacy keys, drop unknown ones and coerce numeric fields of one object.
+    fn fields(
+        &mut self,
+        obj: &mut Map<String, Value>,
+        path: &str,
+        known: &[&str],
+        numeric: &[&str],
+    ) -> Result<()> {
+        let unknown: Vec<String> = obj
+            .keys()
+            .filter(|key| !known.contains(&key.as_str()))
+            .cloned()
+            .collect();
+        for key in unknown {
+            let camel = snake_to_camel_case(&key);
+            if known.contains(&camel.as_str()) && !obj.contains_key(&camel) {
+                self.apply(CompatRule::RenameLegacyKeys, path, &key)?;
+                let value = obj.remove(&key).expect("key was listed");
+                obj.insert(camel, value);
+            } else {
+                self.apply(CompatRule::DropUnknownFields, path, &key)?;
+                obj.remove(&key);
+            }
+        }
+
+        for key in numeric {
+            if let Some(value) = obj.get_mut(*key) {
+                self.coerce_number(value, path, key)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn coerce_number(&mut self, value: &mut Value, path: &str, key: &str) -> Result<()> {
+        let Value::String(s) = value else {
+            return Ok(());
+        };
+        let number = match s.trim().parse::<u64>() {
+            Ok(n) => Value::from(n),
+            Err(_) => match s.trim().parse::<i64>() {
+                Ok(n) => Value::from(n),
+                // Left for deserialization to report
+                Err(_) => return Ok(()),
+            },
+        };
+        self.apply(CompatRule::CoerceNumericStrings, path, key)?;
+        *value = number;
+        Ok(())
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else if key.starts_with('[') {
+        format!("{path}{key}")
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn snake_to_camel_case(key: &str) -> String {
+    let mut camel = String::with_capacity(key.len());
+    let mut upper = false;
+    for ch in key.chars() {
+        if ch == '_' {
+            upper = true;
+        } else if upper {
+            camel.push(ch.to_ascii_uppercase());
+            upper = false;
+        } else {
+            camel.push(ch);
+        }
+    }
+    camel
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    /// A block that needs no rules, with `fields` merged into the block object.
+    fn block_with(fields: Value) -> Value {
+        let mut block = json!({
+            "previousBlockhash": "JCu1pPv9ie31W2iiCfbgw24dE6Qhrd8hSqyfuTX72KA9",
+            "blockhash": "13VwGEw4m1HcZeSVqxJvVYZu91DV2Jkk7sY5uv6WCHn9",
+            "parentSlot": 299999999,
+            "transactions": [],
+            "rewards": [],
+            "blockTime": 1717000000,
+            "blockHeight": 280000000,
+        });
+        let Value::Object(fields) = fields else {
+            panic!("fields must be an object");
+        };
+        block.as_object_mut().unwrap().extend(fields);
+        block
+    }
+
+    /// A transaction whose status metadata holds `fields` next to the required ones.
+    fn transaction_with(meta: Value) -> Value {
+        let mut transaction = json!({
+            "transaction": ["", "base64"],
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [10000],
+                "postBalances": [5000],
+            },
+        });
+        let Value::Object(meta) = meta else {
+            panic!("meta must be an object");
+        };
+        transaction["meta"].as_object_mut().unwrap().extend(meta);
+        transaction
+    }
+
+    fn counts(compat: &SchemaCompat) -> Vec<(CompatRule, u64)> {
+        compat
+            .rule_counts()
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    /// Normalize `block` in lenient mode and return it with the per-rule counts.
+    fn lenient(mut block: Value) -> (Value, u64, Vec<(CompatRule, u64)>) {
+        let compat = SchemaCompat::new(SchemaCompatMode::Lenient);
+        let applied = compat.normalize_block(&mut block, 1).unwrap();
+        (block, applied, counts(&compat))
+    }
+
+    /// Normalize `block` in strict mode and return the error, checking nothing was counted.
+    fn strict(mut block: Value) -> String {
+        let compat = SchemaCompat::new(SchemaCompatMode::Strict);
+        let err = compat.normalize_block(&mut block, 1).unwrap_err();
+        assert!(counts(&compat).is_empty());
+        err.to_string()
+    }
+
+    #[test]
+    fn test_clean_block() {
+        let (block, applied, rule_counts) = lenient(block_with(json!({})));
+        assert_eq!(block, block_with(json!({})));
+        assert_eq!(applied, 0);
+        assert!(rule_counts.is_empty());
+
+        let compat = SchemaCompat::new(SchemaCompatMode::Strict);
+        let mut block = block_with(json!({ "transactions": [transaction_with(json!({}))] }));
+        assert_eq!(compat.normalize_block(&mut block, 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_rename_legacy_keys() {
+        let mut legacy = block_with(json!({ "block_height": 280000000 }));
+        legacy.as_object_mut().unwrap().remove("blockHeight");
+        let (block, applied, rule_counts) = lenient(legacy.clone());
+        assert_eq!(block, block_with(json!({})));
+        assert_eq!(applied, 1);
+        assert_eq!(rule_counts, vec![(CompatRule::RenameLegacyKeys, 1)]);
+
+        let err = strict(legacy);
+        assert!(err.contains("`block_height`"), "{err}");
+        assert!(err.contains("rename_legacy_keys"), "{err}");
+
+        // A legacy key next to its camelCase field is dropped instead
+        let (block, _, rule_counts) = lenient(block_with(json!({ "block_height": 1 })));
+        assert_eq!(block, block_with(json!({})));
+        assert_eq!(rule_counts, vec![(CompatRule::DropUnknownFields, 1)]);
+    }
+
+    #[test]
+    fn test_drop_unknown_fields() {
+        let drifted = block_with(json!({
+            "leaderSchedule": [],
+            "transactions": [transaction_with(json!({ "newField": 1 }))],
+            "rewards": [{
+                "pubkey": "JCu1pPv9ie31W2iiCfbgw24dE6Qhrd8hSqyfuTX72KA9",
+                "lamports": 1,
+                "postBalance": 2,
+                "rewardType": "Fee",
+                "commission": null,
+                "epoch": 600,
+            }],
+        }));
+        let (block, applied, rule_counts) = lenient(drifted.clone());
+        assert!(block.get("leaderSchedule").is_none());
+        assert!(block["transactions"][0]["meta"].get("newField").is_none());
+        assert!(block["rewards"][0].get("epoch").is_none());
+        assert_eq!(applied, 3);
+        assert_eq!(rule_counts, vec![(CompatRule::DropUnknownFields, 3)]);
+
+        let err = strict(drifted);
+        assert!(err.contains("`leaderSchedule`"), "{err}");
+        assert!(err.contains("drop_unknown_fields"), "{err}");
+    }
+
+    #[test]
+    fn test_coerce_numeric_strings() {
+        let drifted = block_with(json!({
+            "blockTime": "1717000000",
+            "transactions": [transaction_with(json!({
+                "fee": " 5000 ",
+                "preBalances": ["10000"],
+                "computeUnitsConsumed": "-1",
+                "postBalances": ["not a number"],
+            }))],
+        }));
+        let (block, applied, rule_counts) = lenient(drifted.clone());
+        assert_eq!(block["blockTime"], json!(1717000000));
+        let meta = &block["transactions"][0]["meta"];
+        assert_eq!(meta["fee"], json!(5000));
+        assert_eq!(meta["preBalances"], json!([10000]));
+        assert_eq!(meta["computeUnitsConsumed"], json!(-1));
+        // Left for deserialization to report
+        assert_eq!(meta["postBalances"], json!(["not a number"]));
+        assert_eq!(applied, 4);
+        assert_eq!(rule_counts, vec![(CompatRule::CoerceNumericStrings, 4)]);
+
+        let err = strict(drifted);
+        assert!(err.contains("`blockTime`"), "{err}");
+        assert!(err.contains("coerce_numeric_strings"), "{err}");
+    }
+
+    #[test]
+    fn test_default_missing_fields() {
+        let mut missing = block_with(json!({ "rewards": null }));
+        missing.as_object_mut().unwrap().remove("transactions");
+        let (block, applied, rule_counts) = lenient(missing.clone());
+        assert_eq!(block, block_with(json!({})));
+        assert_eq!(applied, 2);
+        assert_eq!(rule_counts, vec![(CompatRule::DefaultMissingFields, 2)]);
+
+        let err = strict(missing);
+        assert!(err.contains("`transactions`"), "{err}");
+        assert!(err.contains("default_missing_fields"), "{err}");
+
+        // `err` and `status` are derived from each other
+        let mut only_err =
+            transaction_with(json!({ "err": { "InstructionError": [0, "Custom"] } }));
+        only_err["meta"].as_object_mut().unwrap().remove("status");
+        let mut only_status = transaction_with(json!({ "status": { "Err": "AccountInUse" } }));
+        only_status["meta"].as_object_mut().unwrap().remove("err");
+        let drifted = block_with(json!({ "transactions": [only_err, only_status] }));
+        let (block, applied, rule_counts) = lenient(drifted.clone());
+        assert_eq!(
+            block["transactions"][0]["meta"]["status"],
+            json!({ "Err": { "InstructionError": [0, "Custom"] } })
+        );
+        assert_eq!(
+            block["transactions"][1]["meta"]["err"],
+            json!("AccountInUse")
+        );
+        assert_eq!(applied, 2);
+        assert_eq!(rule_counts, vec![(CompatRule::DefaultMissingFields, 2)]);
+
+        let err = strict(drifted);
+        assert!(err.contains("`transactions[0].meta.status`"), "{err}");
+    }
+
+    #[test]
+    fn test_unknown_reward_types() {
+        let reward = |reward_type: &str| {
+            json!({
+                "pubkey": "JCu1pPv9ie31W2iiCfbgw24dE6Qhrd8hSqyfuTX72KA9",
+                "lamports": -10,
+                "postBalance": 2,
+                "rewardType": reward_type,
+                "commission": null,
+            })
+        };
+        let drifted = block_with(json!({
+            "rewards": [reward("Rent"), reward("staking"), reward("Bonus")],
+        }));
+        let (block, applied, rule_counts) = lenient(drifted.clone());
+        assert_eq!(block["rewards"][0]["rewardType"], json!("Rent"));
+        assert_eq!(block["rewards"][1]["rewardType"], json!("Staking"));
+        assert_eq!(block["rewards"][2]["rewardType"], Value::Null);
+        assert_eq!(applied, 2);
+        assert_eq!(rule_counts, vec![(CompatRule::UnknownRewardTypes, 2)]);
+
+        let err = strict(drifted);
+        assert!(err.contains("`rewards[1].rewardType`"), "{err}");
+        assert!(err.contains("unknown_reward_types"), "{err}");
+    }
+
+    #[test]
+    fn test_rule_counts_are_shared() {
+        let compat = SchemaCompat::new(SchemaCompatMode::Lenient);
+        let clone = compat.clone();
+        let mut drifted = block_with(json!({ "fee": 1, "blockTime": "1" }));
+        assert_eq!(compat.normalize_block(&mut drifted.clone(), 1).unwrap(), 2);
+        assert_eq!(clone.normalize_block(&mut drifted, 2).unwrap(), 2);
+        assert_eq!(
+            counts(&compat),
+            vec![
+                (CompatRule::DropUnknownFields, 2),
+                (CompatRule::CoerceNumericStrings, 2),
+            ]
+        );
+        assert_eq!(compat.rule_counts(), clone.rule_counts());
+    }
+}
@@ -0,0 +1,644 @@
+use {
+    crate::{
+        block_envelope::decode_envelope,
+        message_decoder::{DecodedPayload, MessageDecoder},
+        queue_consumer::MessageHeaders,
+        schema_compat::SchemaCompat,
+    },
+    anyhow::{anyhow, Context, Result},
+    log::{info, warn},
+    serde::{Deserialize, Serialize},
+    serde_json::{value::RawValue, Value},
+    std::{
+        collections::HashMap,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+};
+
+/// First byte of every message written by a Confluent serializer.
+pub const CONFLUENT_MAGIC_BYTE: u8 = 0;
+
+/// Time limit of a Schema Registry request, so that a stalled registry fails the message
+/// instead of holding up the consumer.
+const REGISTRY_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Name of the protobuf message holding a block, as in `solana-storage-proto`.
+const PROTOBUF_BLOCK_MESSAGE: &str = "ConfirmedBlock";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SchemaType {
+    #[default]
+    Avro,
+    Protobuf,
+    Json,
+}
+
+/// A schema as returned by `GET /schemas/ids/{id}`. Avro schemas omit the type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisteredSchema {
+    #[serde(rename = "schemaType", default)]
+    pub schema_type: SchemaType,
+    pub schema: String,
+}
+
+/// A registered schema parsed into the parts the decoder reads messages with. Schemas are
+/// parsed once, when they are first resolved.
+enum ParsedSchema {
+    /// Names of the top-level messages, in order.
+    Protobuf(Vec<String>),
+    Avro(AvroBlockSchema),
+    Json,
+}
+
+impl ParsedSchema {
+    fn parse(schema: &RegisteredSchema) -> Result<Self> {
+        match schema.schema_type {
+            SchemaType::Protobuf => {
+                let messages = protobuf_top_level_messages(&schema.schema)?;
+                if !messages.iter().any(|name| name == PROTOBUF_BLOCK_MESSAGE) {
+                    return Err(anyhow!(
+                        "Protobuf schema has no top-level {PROTOBUF_BLOCK_MESSAGE} message"
+                    ));
+                }
+                Ok(ParsedSchema::Protobuf(messages))
+            }
+            SchemaType::Avro => AvroBlockSchema::parse(&schema.schema).map(ParsedSchema::Avro),
+            SchemaType::Json => Ok(ParsedSchema::Json),
+        }
+    }
+}
+
+/// Split a Confluent wire-format message into its schema ID and the serialized data.
+pub fn parse_wire_format(data: &[u8]) -> Result<(u32, &[u8])> {
+    match data {
+        [CONFLUENT_MAGIC_BYTE, a, b, c, d, rest @ ..] => {
+            Ok((u32::from_be_bytes([*a, *b, *c, *d]), rest))
+        }
+        [CONFLUENT_MAGIC_BYTE, ..] => Err(anyhow!("Truncated Confluent wire-format header")),
+        _ => Err(anyhow!("Missing Confluent wire-format magic byte")),
+    }
+}
+
+/// Resolves schemas by ID from a Schema Registry, with a local file cache of
+/// `<cache_dir>/<id>.json` files. The cache can stand in for the registry entirely.
+pub struct SchemaRegistryClient {
+    url: Option<String>,
+    cache_dir: Option<PathBuf>,
+    http: reqwest::Client,
+    schemas: Mutex<HashMap<u32, Arc<ParsedSchema>>>,
+}
+
+impl SchemaRegistryClient {
+    pub fn new(url: Option<String>, cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            url: url.map(|url| url.trim_end_matches('/').to_string()),
+            cache_dir,
+            http: reqwest::Client::builder()
+                .timeout(REGISTRY_REQUEST_TIMEOUT)
+                .build()
+                .expect("Failed to build the Schema Registry HTTP client"),
+            schemas: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_schema(&self, id: u32) -> Result<Arc<ParsedSchema>> {
+        if let Some(schema) = self.schemas.lock().unwrap().get(&id) {
+            return Ok(schema.clone());
+        }
+
+        let schema = match self.read_cached(id).await? {
+            Some(schema) => schema,
+            None => {
+                let schema = self.fetch(id).await?;
+                // The cache only saves later fetches, so a read-only cache must not fail decoding
+                if let Err(e) = self.write_cached(id, &schema).await {
+                    warn!("Failed to cache schema {id}: {e:#}");
+                }
+                schema
+            }
+        };
+        let schema = Arc::new(
+            ParsedSchema::parse(&schema)
+                .with_context(|| format!("Unsupported schema {id}"))?,
+        );
+        self.schemas.lock().unwrap().insert(id, schema.clone());
+        Ok(schema)
+    }
+
+    fn cache_path(&self, id: u32) -> Option<PathBuf> {
+        self.cache_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{id}.json")))
+    }
+
+    async fn read_cached(&self, id: u32) -> Result<Option<RegisteredSchema>> {
+        let Some(path) = self.cache_path(id) else {
+            return Ok(None);
+        };
+        match tokio::fs::read(&path).await {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map(Some)
+                .with_context(|| format!("Invalid cached schema file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    async fn write_cached(&self, id: u32, schema: &RegisteredSchema) -> Result<()> {
+        let Some(path) = self.cache_path(id) else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        tokio::fs::write(&path, serde_json::to_vec(schema)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    async fn fetch(&self, id: u32) -> Result<RegisteredSchema> {
+        let url = self
+            .url
+            .as_ref()
+            .ok_or_else(|| anyhow!("Schema {id} is not cached and no registry URL is set"))?;
+        let response = self
+            .http
+            .get(format!("{url}/schemas/ids/{id}"))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to fetch schema {id} from {url}"))?;
+        let body = response.bytes().await?;
+        info!("Fetched schema {id} from {url}");
+        serde_json::from_slice(&body).with_context(|| format!("Invalid schema {id} from {url}"))
+    }
+}
+
+/// Decodes messages written by Confluent serializers. Supported schemas:
+/// - Protobuf: the `ConfirmedBlock` message, decoded by the inner protobuf decoder, which
+///   reads the slot from the `slot` header.
+/// - Avro: a flat record with a `slot` (or `blockID`) long, a `block` string or bytes field
+///   holding the getBlock JSON, and an optional `entries` field holding the entries JSON.
+/// - JSON: any payload understood by the inner JSON decoder.
+///
+/// Other schemas are rejected when they are first resolved.
+pub struct ConfluentMessageDecoder {
+    registry: SchemaRegistryClient,
+    json: Arc<dyn MessageDecoder + Send + Sync>,
+    protobuf: Arc<dyn MessageDecoder + Send + Sync>,
+    schema_compat: SchemaCompat,
+}
+
+impl ConfluentMessageDecoder {
+    pub fn new(
+        registry: SchemaRegistryClient,
+        json: Arc<dyn MessageDecoder + Send + Sync>,
+        protobuf: Arc<dyn MessageDecoder + Send + Sync>,
+    ) -> Self {
+        Self {
+            registry,
+            json,
+            protobuf,
+            schema_compat: SchemaCompat::default(),
+        }
+    }
+
+    /// Handle schema drift in Avro blocks with the given settings. JSON payloads use the
+    /// settings of the inner JSON decoder.
+    pub fn with_schema_compat(mut self, schema_compat: SchemaCompat) -> Self {
+        self.schema_compat = schema_compat;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl MessageDecoder for ConfluentMessageDecoder {
+    async fn decode(&self, data: &[u8], headers: &MessageHeaders) -> Result<DecodedPayload> {
+        let (schema_id, data) = parse_wire_format(data)?;
+        let schema = self.registry.get_schema(schema_id).await?;
+
+        match schema.as_ref() {
+            ParsedSchema::Protobuf(messages) => {
+                let mut reader = AvroReader::new(data);
+                let message_indexes = read_protobuf_message_indexes(&mut reader)?;
+                let message_name = protobuf_message_name(messages, &message_indexes)
+                    .with_context(|| {
+                        format!("Unsupported protobuf message in schema {schema_id}")
+                    })?;
+                if message_name != PROTOBUF_BLOCK_MESSAGE {
+                    return Err(anyhow!(
+                        "Unsupported protobuf message {message_name} in schema {schema_id}"
+                    ));
+                }
+                self.protobuf.decode(reader.remaining(), headers).await
+            }
+            ParsedSchema::Avro(schema) => decode_avro_block(schema, data, &self.schema_compat)
+                .with_context(|| format!("Failed to decode Avro block with schema {schema_id}")),
+            ParsedSchema::Json => self.json.decode(data, headers).await,
+        }
+    }
+}
+
+/// Confluent prefixes protobuf data with the path of the message in the schema: a zigzag
+/// count followed by that many zigzag indexes, where a zero count means the first message.
+fn read_protobuf_message_indexes(reader: &mut AvroReader) -> Result<Vec<i64>> {
+    let count = reader.read_long()?;
+    if count == 0 {
+        return Ok(vec![0]);
+    }
+    (0..count).map(|_| reader.read_long()).collect()
+}
+
+/// Name of the top-level message selected by the message indexes. Nested messages are not
+/// supported.
+fn protobuf_message_name<'a>(messages: &'a [String], message_indexes: &[i64]) -> Result<&'a str> {
+    let [index] = message_indexes else {
+        return Err(anyhow!("Nested protobuf messages are not supported"));
+    };
+    usize::try_from(*index)
+        .ok()
+        .and_then(|index| messages.get(index))
+        .map(String::as_str)
+        .ok_or_else(|| anyhow!("No top-level message at index {index}"))
+}
+
+/// Names of the top-level messages of a `.proto` schema, in order. Comments and string
+/// literals are skipped, so they cannot hide or add messages.
+fn protobuf_top_level_messages(schema: &str) -> Result<Vec<String>> {
+    let mut depth = 0usize;
+    let mut names = vec![];
+    let mut tokens = protobuf_tokens(schema)?.into_iter();
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            "{" => depth += 1,
+            "}" => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| anyhow!("Unbalanced braces in protobuf schema"))?;
+            }
+            "message" if depth == 0 => match tokens.next() {
+                Some(name) if is_protobuf_identifier(&name) => names.push(name),
+                name => return Err(anyhow!("Invalid protobuf message name: {name:?}")),
+            },
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(anyhow!("Unbalanced braces in protobuf schema"));
+    }
+    Ok(names)
+}
+
+fn is_protobuf_identifier(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split a `.proto` schema into words and punctuation, dropping comments and string literals.
+fn protobuf_tokens(schema: &str) -> Result<Vec<String>> {
+    let mut tokens = vec![];
+    let mut chars = schema.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.next_if_eq(&'/').is_some() => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            }
+            '/' if chars.next_if_eq(&'*').is_some() => loop {
+                match chars.next() {
+                    Some('*') if chars.next_if_eq(&'/').is_some() => break,
+                    Some(_) => {}
+                    None => return Err(anyhow!("Unterminated comment in protobuf schema")),
+                }
+            },
+            '"' | '\'' => loop {
+                match chars.next() {
+                    Some('\\') => {
+                        chars.next();
+                    }
+                    Some(end) if end == c => break,
+                    Some(_) => {}
+                    None => return Err(anyhow!("Unterminated string in protobuf schema")),
+                }
+            },
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '.' => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+                {
+                    word.push(c);
+                }
+                tokens.push(word);
+            }
+            c => tokens.push(c.to_string()),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Values of the fields of a block record. Types the mapping never uses are skipped.
+enum AvroValue {
+    Null,
+    Long(i64),
+    Bytes(Vec<u8>),
+    Other,
+}
+
+/// Primitive Avro types of the fields of a flat record. Unions list their branches.
+#[derive(Debug)]
+enum AvroType {
+    Null,
+    Boolean,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    Union(Vec<AvroType>),
+}
+
+impl AvroType {
+    fn parse(schema: &Value) -> Result<Self> {
+        let avro_type = Self::parse_type(schema)?;
+        if let AvroType::Union(branches) = &avro_type {
+            if branches
+                .iter()
+                .any(|branch| matches!(branch, AvroType::Union(_)))
+            {
+                return Err(anyhow!("Avro unions may not contain unions"));
+            }
+        }
+        Ok(avro_type)
+    }
+
+    fn parse_type(schema: &Value) -> Result<Self> {
+        match schema {
+            Value::String(name) => match name.as_str() {
+                "null" => Ok(AvroType::Null),
+                "boolean" => Ok(AvroType::Boolean),
+                "int" | "long" => Ok(AvroType::Long),
+                "float" => Ok(AvroType::Float),
+                "double" => Ok(AvroType::Double),
+                "bytes" | "string" => Ok(AvroType::Bytes),
+                other => Err(anyhow!("Unsupported Avro type: {other}")),
+            },
+            Value::Array(branches) => Ok(AvroType::Union(
+                branches
+                    .iter()
+                    .map(AvroType::parse_type)
+                    .collect::<Result<_>>()?,
+            )),
+            // Primitive types with attributes, e.g. { "type": "long", "logicalType": ... }
+            Value::Object(obj) => match obj.get("type") {
+                Some(inner @ Value::String(_)) => AvroType::parse_type(inner),
+                _ => Err(anyhow!("Unsupported Avro type: {schema}")),
+            },
+            other => Err(anyhow!("Invalid Avro type: {other}")),
+        }
+    }
+}
+
+struct AvroReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> AvroReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(anyhow!("Unexpected end of Avro data"));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    /// Zigzag encoded variable-length integer.
+    fn read_long(&mut self) -> Result<i64> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        Err(anyhow!("Avro integer is too long"))
+    }
+
+    fn read(&mut self, avro_type: &AvroType) -> Result<AvroValue> {
+        Ok(match avro_type {
+            AvroType::Null => AvroValue::Null,
+            AvroType::Boolean => {
+                self.take(1)?;
+                AvroValue::Other
+            }
+            AvroType::Long => AvroValue::Long(self.read_long()?),
+            AvroType::Float => {
+                self.take(4)?;
+                AvroValue::Other
+            }
+            AvroType::Double => {
+                self.take(8)?;
+                AvroValue::Other
+            }
+            AvroType::Bytes => {
+                let len = usize::try_from(self.read_long()?)
+                    .map_err(|_| anyhow!("Negative Avro length"))?;
+                AvroValue::Bytes(self.take(len)?.to_vec())
+            }
+            AvroType::Union(branches) => {
+                let index = self.read_long()?;
+                let branch = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| branches.get(index))
+                    .ok_or_else(|| anyhow!("Invalid Avro union branch: {index}"))?;
+                self.read(branch)?
+            }
+        })
+    }
+}
+
+/// Whether values of the type are always read as `AvroValue::Long`.
+fn is_long(avro_type: &AvroType) -> bool {
+    matches!(avro_type, AvroType::Long)
+}
+
+/// Whether values of the type are read as `AvroValue::Bytes`, or as null for optional fields.
+fn is_bytes(avro_type: &AvroType, optional: bool) -> bool {
+    match avro_type {
+        AvroType::Bytes => true,
+        AvroType::Null => optional,
+        AvroType::Union(branches) => branches.iter().all(|branch| is_bytes(branch, optional)),
+        _ => false,
+    }
+}
+
+/// The fields of an Avro block record, in order.
+struct AvroBlockSchema {
+    fields: Vec<(String, AvroType)>,
+}
+
+impl AvroBlockSchema {
+    /// Parse a record schema, rejecting field types the reader does not support and records
+    /// the block cannot be read from.
+    fn parse(schema: &str) -> Result<Self> {
+        let schema: Value = serde_json::from_str(schema).context("Invalid Avro schema")?;
+        if schema["type"] != "record" {
+            return Err(anyhow!("Avro block schema must be a record"));
+        }
+        let mut fields: Vec<(String, AvroType)> = vec![];
+        for field in schema["fields"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Avro block schema has no fields"))?
+        {
+            let name = field["name"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Avro field without a name"))?;
+            if fields.iter().any(|(existing, _)| existing == name) {
+                return Err(anyhow!("Duplicate Avro field {name}"));
+            }
+            let avro_type = AvroType::parse(&field["type"])
+                .with_context(|| format!("Unsupported type of Avro field {name}"))?;
+            fields.push((name.to_string(), avro_type));
+        }
+
+        let schema = Self { fields };
+        match schema.field("slot").or_else(|| schema.field("blockID")) {
+            Some(avro_type) if is_long(avro_type) => {}
+            Some(_) => return Err(anyhow!("Avro slot field must be an int or a long")),
+            None => return Err(anyhow!("Avro block record has no slot field")),
+        }
+        match schema.field("block") {
+            Some(avro_type) if is_bytes(avro_type, false) => {}
+            Some(_) => return Err(anyhow!("Avro block field must be a string or bytes")),
+            None => return Err(anyhow!("Avro block record has no block field")),
+        }
+        match schema.field("entries") {
+            Some(avro_type) if !is_bytes(avro_type, true) => {
+                return Err(anyhow!(
+                    "Avro entries field must be a string or bytes, optionally nullable"
+                ))
+            }
+            _ => {}
+        }
+        Ok(schema)
+    }
+
+    fn field(&self, name: &str) -> Option<&AvroType> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, avro_type)| avro_type)
+    }
+}
+
+/// The top-level envelope of an Avro block record: the block JSON with `blockID` and `entries`
+/// spliced in as text, so that the block is parsed only once, by `decode_envelope`.
+fn avro_envelope(slot: u64, block: &[u8], entries: Option<&[u8]>) -> Result<String> {
+    let block = std::str::from_utf8(block).context("Block JSON is not valid UTF-8")?;
+    let fields = block
+        .trim_start()
+        .strip_prefix('{')
+        .ok_or_else(|| anyhow!("Block JSON must be an object"))?;
+    let mut envelope = format!("{{\"blockID\":{slot}");
+    if let Some(entries) = entries {
+        let entries = std::str::from_utf8(entries).context("Entries JSON is not valid UTF-8")?;
+        let entries: &RawValue =
+            serde_json::from_str(entries).context("Failed to parse entries JSON")?;
+        envelope.push_str(",\"entries\":");
+        envelope.push_str(entries.get());
+    }
+    if !fields.trim_start().starts_with('}') {
+        envelope.push(',');
+    }
+    envelope.push_str(fields);
+    Ok(envelope)
+}
+
+fn decode_avro_block(
+    schema: &AvroBlockSchema,
+    data: &[u8],
+    schema_compat: &SchemaCompat,
+) -> Result<DecodedPayload> {
+    let mut reader = AvroReader::new(data);
+    let mut values = HashMap::new();
+    for (name, avro_type) in &schema.fields {
+        let value = reader
+            .read(avro_type)
+            .with_context(|| format!("Failed to read Avro field {name}"))?;
+        values.insert(name.as_str(), value);
+    }
+    if !reader.remaining().is_empty() {
+        return Err(anyhow!(
+            "{} bytes left after the Avro block record",
+            reader.remaining().len()
+        ));
+    }
+    let slot = match values.get("slot").or_else(|| values.get("blockID")) {
+        Some(AvroValue::Long(slot)) => {
+            u64::try_from(*slot).map_err(|_| anyhow!("Invalid slot: {slot}"))?
+        }
+        _ => return Err(anyhow!("Avro block record has no slot field")),
+    };
+    let block = match values.get("block") {
+        Some(AvroValue::Bytes(json)) => json,
+        _ => return Err(anyhow!("Avro block record has no block field")),
+    };
+    let entries = match values.get("entries") {
+        Some(AvroValue::Bytes(json)) => Some(json.as_slice()),
+        _ => None,
+    };
+
+    let envelope = decode_envelope(avro_envelope(slot, block, entries)?, schema_compat)?;
+    Ok(DecodedPayload::BlockWithEntries(
+        envelope.block_id,
+        envelope.block,
+        envelope.entries,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_avro_envelope_splices_block_id_and_entries() {
+        let envelope = avro_envelope(
+            7,
+            b" {\"blockhash\":\"abc\",\"parentSlot\":6}",
+            Some(b"[ ]"),
+        )
+        .unwrap();
+        assert_eq!(
+            envelope,
+            r#"{"blockID":7,"entries":[ ],"blockhash":"abc","parentSlot":6}"#
+        );
+        serde_json::from_str::<Value>(&envelope).unwrap();
+    }
+
+    #[test]
+    fn test_avro_envelope_of_an_empty_block() {
+        let envelope = avro_envelope(7, b"{ }", None).unwrap();
+        assert_eq!(envelope, r#"{"blockID":7 }"#);
+        serde_json::from_str::<Value>(&envelope).unwrap();
+    }
+
+    #[test]
+    fn test_avro_envelope_rejects_invalid_parts() {
+        assert!(avro_envelope(7, b"[]", None).is_err());
+        // Entries must be one JSON value, not text that adds keys to the envelope
+        assert!(avro_envelope(7, b"{}", Some(b"[], \"encoding\": \"base64\"")).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use {
+    anyhow::{anyhow, Context, Result},
+    rayon::{prelude::*, ThreadPool, ThreadPoolBuilder},
+    solana_sdk::{signature::Signature, transaction::VersionedTransaction},
+    solana_transaction_status::{ConfirmedBlock, TransactionWithStatusMeta},
+    std::{num::NonZeroUsize, str::FromStr, sync::Arc, thread},
+};
+
+/// What to do with blocks holding transactions whose signatures do not verify.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignatureVerificationPolicy {
+    /// Do not verify signatures.
+    #[default]
+    Off,
+    /// Fail the whole block.
+    Reject,
+    /// Store the block in the quarantine table only, see `LedgerStorage::quarantine_block`.
+    Quarantine,
+}
+
+impl FromStr for SignatureVerificationPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(SignatureVerificationPolicy::Off),
+            "reject" => Ok(SignatureVerificationPolicy::Reject),
+            "quarantine" => Ok(SignatureVerificationPolicy::Quarantine),
+            other => Err(anyhow!(
+                "Unsupported signature verification policy: {other}"
+            )),
+        }
+    }
+}
+
+/// A transaction of a block whose signatures do not verify.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSignature {
+    /// Index of the transaction in the block.
+    pub transaction_index: usize,
+    /// First signature of the transaction.
+    pub signature: Signature,
+    /// Index of the first signature that failed, or `None` if the transaction does not carry
+    /// as many signatures as its message requires.
+    pub signature_index: Option<usize>,
+}
+
+/// Verifies the ed25519 signatures of all transactions of a block against their serialized
+/// messages, on a pool of CPU threads shared by all blocks. Clones share the pool.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureVerifier {
+    pub policy: SignatureVerificationPolicy,
+    /// Only built when verification is enabled.
+    pool: Option<Arc<ThreadPool>>,
+}
+
+/// One verification thread per available CPU.
+pub fn default_verification_threads() -> NonZeroUsize {
+    thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+}
+
+impl SignatureVerifier {
+    pub fn new(policy: SignatureVerificationPolicy, threads: NonZeroUsize) -> Result<Self> {
+        let pool = match policy {
+            SignatureVerificationPolicy::Off => None,
+            _ => Some(Arc::new(
+                ThreadPoolBuilder::new()
+                    .num_threads(threads.get())
+                    .thread_name(|index| format!("sigverify-{index}"))
+                    .build()
+                    .context("Failed to start signature verification threads")?,
+            )),
+        };
+        Ok(Self { policy, pool })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.policy != SignatureVerificationPolicy::Off
+    }
+
+    /// Verify a block off the async runtime. The block is handed back along with the
+    /// transactions that failed, in block order.
+    pub async fn verify_block(
+        &self,
+        block: ConfirmedBlock,
+    ) -> Result<(ConfirmedBlock, Vec<InvalidSignature>)> {
+        let Some(pool) = self.pool.clone() else {
+            return Ok((block, vec![]));
+        };
+        tokio::task::spawn_blocking(move || {
+            let invalid = pool.install(|| verify_transactions(&block.transactions));
+            (block, invalid)
+        })
+        .await
+        .context("Signature verification task failed")
+    }
+}
+
+fn verify_transactions(transactions: &[TransactionWithStatusMeta]) -> Vec<InvalidSignature> {
+    transactions
+        .par_iter()
+        .enumerate()
+        .filter_map(|(index, transaction)| verify_transaction(index, transaction))
+        .collect()
+}
+
+fn verify_transaction(
+    transaction_index: usize,
+    transaction: &TransactionWithStatusMeta,
+) -> Option<InvalidSignature> {
+    let owned;
+    let transaction: &VersionedTransaction = match transaction {
+        TransactionWithStatusMeta::Complete(transaction) => &transaction.transaction,
+        TransactionWithStatusMeta::MissingMetadata(transaction) => {
+            owned = VersionedTransaction::from(transaction.clone());
+            &owned
+        }
+    };
+    let signature = transaction.signatures.first().copied().unwrap_or_default();
+
+    // Signatures are verified pairwise with the signer keys, so missing ones would go unnoticed
+    let required = transaction.message.header().num_required_signatures as usize;
+    if transaction.signatures.len() != required {
+        return Some(InvalidSignature {
+            transaction_index,
+            signature,
+            signature_index: None,
+        });
+    }
+
+    transaction
+        .verify_with_results()
+        .iter()
+        .position(|verified| !verified)
+        .map(|signature_index| InvalidSignature {
+            transaction_index,
+            signature,
+            signature_index: Some(signature_index),
+        })
+}
@@ -0,0 +1,507 @@
+use {
+    anyhow::{anyhow, Context, Result},
+    serde_json::Value,
+    solana_block_decoder::block::encoded_block::{
+        EncodedConfirmedBlock, EncodedTransaction, EncodedTransactionWithStatusMeta,
+    },
+    solana_message::MessageHeader,
+    solana_sdk::pubkey::Pubkey,
+    solana_system_interface::{instruction::SystemInstruction, program as system_program},
+    solana_transaction_status::{
+        option_serializer::OptionSerializer, parse_instruction::ParsedInstruction,
+        TransactionBinaryEncoding, UiCompiledInstruction, UiInnerInstructions, UiInstruction,
+        UiLoadedAddresses, UiMessage, UiParsedInstruction, UiParsedMessage, UiRawMessage,
+        UiTransaction, UiTransactionEncoding,
+    },
+    std::collections::HashMap,
+};
+
+/// The getBlock encoding a single transaction was fetched with.
+pub fn transaction_encoding(transaction: &EncodedTransaction) -> Result<UiTransactionEncoding> {
+    match transaction {
+        EncodedTransaction::LegacyBinary(_)
+        | EncodedTransaction::Binary(_, TransactionBinaryEncoding::Base58) => {
+            Ok(UiTransactionEncoding::Base58)
+        }
+        EncodedTransaction::Binary(_, TransactionBinaryEncoding::Base64) => {
+            Ok(UiTransactionEncoding::Base64)
+        }
+        EncodedTransaction::Json(UiTransaction {
+            message: UiMessage::Raw(_),
+            ..
+        }) => Ok(UiTransactionEncoding::Json),
+        EncodedTransaction::Json(UiTransaction {
+            message: UiMessage::Parsed(_),
+            ..
+        }) => Ok(UiTransactionEncoding::JsonParsed),
+        EncodedTransaction::Accounts(_) => Err(anyhow!(
+            "Transactions fetched with transactionDetails=accounts cannot be ingested"
+        )),
+    }
+}
+
+/// Check that every transaction of a block uses the encoding stated by its envelope.
+pub fn check_transaction_encoding(
+    block: &EncodedConfirmedBlock,
+    expected: UiTransactionEncoding,
+) -> Result<()> {
+    // `binary` is the legacy name of base58
+    let expected = match expected {
+        UiTransactionEncoding::Binary => UiTransactionEncoding::Base58,
+        other => other,
+    };
+    for (index, tx) in block.transactions.iter().enumerate() {
+        let actual = transaction_encoding(&tx.transaction)
+            .with_context(|| format!("transaction index: {index}"))?;
+        if actual != expected {
+            return Err(anyhow!(
+                "Transaction encoding {actual:?} does not match the stated {expected:?} \
+                 (transaction index: {index})"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Rewrite the transactions of a block into a single encoding that `convert_block` can decode:
+/// - base58 transactions are re-encoded as base64, base64 ones are kept as they are;
+/// - jsonParsed transactions are rebuilt as raw json messages.
+///
+/// Mixing binary and json transactions in one block is rejected.
+pub fn normalize_transaction_encoding(
+    mut block: EncodedConfirmedBlock,
+) -> Result<(EncodedConfirmedBlock, UiTransactionEncoding)> {
+    let mut block_encoding: Option<UiTransactionEncoding> = None;
+    for (index, tx) in block.transactions.iter_mut().enumerate() {
+        let encoding =
+            normalize_transaction(tx).with_context(|| format!("transaction index: {index}"))?;
+        match block_encoding {
+            None => block_encoding = Some(encoding),
+            Some(block_encoding) if block_encoding != encoding => {
+                return Err(anyhow!(
+                    "Block mixes {block_encoding:?} and {encoding:?} transactions \
+                     (transaction index: {index})"
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+    // Blocks without transactions decode the same way with any encoding
+    Ok((block, block_encoding.unwrap_or(UiTransactionEncoding::Json)))
+}
+
+/// Normalize a single transaction in place, returning the encoding it now uses.
+fn normalize_transaction(
+    tx: &mut EncodedTransactionWithStatusMeta,
+) -> Result<UiTransactionEncoding> {
+    match &mut tx.transaction {
+        EncodedTransaction::LegacyBinary(blob)
+        | EncodedTransaction::Binary(blob, TransactionBinaryEncoding::Base58) => {
+            let bytes = bs58::decode(blob.as_str())
+                .into_vec()
+                .context("Invalid base58 transaction")?;
+            tx.transaction = EncodedTransaction::Binary(
+                base64::encode(bytes),
+                TransactionBinaryEncoding::Base64,
+            );
+            Ok(UiTransactionEncoding::Base64)
+        }
+        // Invalid base64 is reported when the block is decoded
+        EncodedTransaction::Binary(_, TransactionBinaryEncoding::Base64) => {
+            Ok(UiTransactionEncoding::Base64)
+        }
+        EncodedTransaction::Json(UiTransaction {
+            message: UiMessage::Raw(_),
+            ..
+        }) => Ok(UiTransactionEncoding::Json),
+        EncodedTransaction::Json(ui_transaction) => {
+            let UiMessage::Parsed(parsed) = &ui_transaction.message else {
+                unreachable!("raw messages are handled above");
+            };
+            let (raw, account_keys, loaded_addresses) = raw_message_from_parsed(parsed)?;
+            ui_transaction.message = UiMessage::Raw(raw);
+            if let Some(meta) = tx.meta.as_mut() {
+                if let OptionSerializer::Some(inner_instructions) = &mut meta.inner_instructions {
+                    for inner in inner_instructions.iter_mut() {
+                        *inner = UiInnerInstructions {
+                            index: inner.index,
+                            instructions: compile_instructions(&inner.instructions, &account_keys)
+                                .context("Failed to convert inner instructions")?
+                                .into_iter()
+                                .map(UiInstruction::Compiled)
+                                .collect(),
+                        };
+                    }
+                }
+                // jsonParsed responses list loaded addresses among the account keys instead
+                if !matches!(meta.loaded_addresses, OptionSerializer::Some(_)) {
+                    meta.loaded_addresses = OptionSerializer::Some(loaded_addresses);
+                }
+            }
+            Ok(UiTransactionEncoding::Json)
+        }
+        EncodedTransaction::Accounts(_) => Err(anyhow!(
+            "Transactions fetched with transactionDetails=accounts cannot be ingested"
+        )),
+    }
+}
+
+/// Rebuild the raw message of a jsonParsed transaction. Also returns all account keys,
+/// including loaded ones, in message order, and the addresses loaded from lookup tables.
+///
+/// Instructions that the RPC node parsed into program-specific JSON are rebuilt from their
+/// parsed fields where the program is known, see `rebuild_parsed_instruction`.
+fn raw_message_from_parsed(
+    parsed: &UiParsedMessage,
+) -> Result<(UiRawMessage, Vec<String>, UiLoadedAddresses)> {
+    let mut static_keys = vec![];
+    let mut loaded_addresses = UiLoadedAddresses {
+        writable: vec![],
+        readonly: vec![],
+    };
+    let mut signers = vec![];
+    let mut non_signers = vec![];
+    for account in &parsed.account_keys {
+        let from_lookup_table = matches!(
+            account.source,
+            Some(solana_transaction_status::parse_accounts::ParsedAccountSource::LookupTable)
+        );
+        if from_lookup_table {
+            if account.writable {
+                loaded_addresses.writable.push(account.pubkey.clone());
+            } else {
+                loaded_addresses.readonly.push(account.pubkey.clone());
+            }
+        } else {
+            static_keys.push(account.pubkey.clone());
+            if account.signer {
+                signers.push(account.writable);
+            } else {
+                non_signers.push(account.writable);
+            }
+        }
+    }
+
+    // Reserved accounts may be reported as read-only even when the message lists them as
+    // writable, so each read-only section starts after the last writable account.
+    let readonly_count =
+        |writable: &[bool]| writable.len() - writable.iter().rposition(|w| *w).map_or(0, |i| i + 1);
+    let header = MessageHeader {
+        num_required_signatures: u8::try_from(signers.len())?,
+        num_readonly_signed_accounts: u8::try_from(readonly_count(&signers))?,
+        num_readonly_unsigned_accounts: u8::try_from(readonly_count(&non_signers))?,
+    };
+
+    let account_keys: Vec<String> = static_keys
+        .iter()
+        .chain(&loaded_addresses.writable)
+        .chain(&loaded_addresses.readonly)
+        .cloned()
+        .collect();
+    let instructions = compile_instructions(&parsed.instructions, &account_keys)?;
+
+    Ok((
+        UiRawMessage {
+            header,
+            account_keys: static_keys,
+            recent_blockhash: parsed.recent_blockhash.clone(),
+            instructions,
+            address_table_lookups: parsed.address_table_lookups.clone(),
+        },
+        account_keys,
+        loaded_addresses,
+    ))
+}
+
+fn compile_instructions(
+    instructions: &[UiInstruction],
+    account_keys: &[String],
+) -> Result<Vec<UiCompiledInstruction>> {
+    let indexes: HashMap<&str, u8> = account_keys
+        .iter()
+        .enumerate()
+        .filter_map(|(i, key)| Some((key.as_str(), u8::try_from(i).ok()?)))
+        .collect();
+    let index_of = |key: &str| {
+        indexes
+            .get(key)
+            .copied()
+            .ok_or_else(|| anyhow!("Account {key} is not in the account keys of the message"))
+    };
+
+    instructions
+        .iter()
+        .map(|instruction| match instruction {
+            UiInstruction::Compiled(compiled) => Ok(compiled.clone()),
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(partial)) => {
+                Ok(UiCompiledInstruction {
+                    program_id_index: index_of(&partial.program_id)?,
+                    accounts: partial
+                        .accounts
+                        .iter()
+                        .map(|account| index_of(account))
+                        .collect::<Result<_>>()?,
+                    data: partial.data.clone(),
+                    stack_height: partial.stack_height,
+                })
+            }
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) => {
+                let (data, accounts) = rebuild_parsed_instruction(parsed)?;
+                Ok(UiCompiledInstruction {
+                    program_id_index: index_of(&parsed.program_id)?,
+                    accounts: accounts
+                        .iter()
+                        .map(|account| index_of(account))
+                        .collect::<Result<_>>()?,
+                    data: bs58::encode(data).into_string(),
+                    stack_height: parsed.stack_height,
+                })
+            }
+        })
+        .collect()
+}
+
+/// Rebuild the raw data and the account keys of an instruction that the RPC node returned in
+/// parsed form. Only System program instructions are rebuilt: other programs either drop fields
+/// from their parsed form or would need their own instruction encoders.
+fn rebuild_parsed_instruction(parsed: &ParsedInstruction) -> Result<(Vec<u8>, Vec<String>)> {
+    let context = || {
+        format!(
+            "Cannot rebuild the raw data of a parsed {} instruction ({})",
+            parsed.program, parsed.program_id
+        )
+    };
+    if parsed.program_id != system_program::id().to_string() {
+        return Err(anyhow!("Unsupported program")).with_context(context);
+    }
+    let (instruction, accounts) =
+        rebuild_system_instruction(&parsed.parsed).with_context(context)?;
+    Ok((bincode::serialize(&instruction)?, accounts))
+}
+
+/// Reverse of `parse_system` in solana-transaction-status: the instruction and its accounts,
+/// in instruction order, from the `type` and `info` of a parsed System instruction.
+fn rebuild_system_instruction(parsed: &Value) -> Result<(SystemInstruction, Vec<String>)> {
+    let info = &parsed["info"];
+    let accounts = |names: &[&str]| -> Result<Vec<String>> {
+        names
+            .iter()
+            .map(|name| info_str(info, name).map(str::to_string))
+            .collect()
+    };
+    let instruction_type = parsed["type"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Missing instruction type"))?;
+    let rebuilt = match instruction_type {
+        "createAccount" => (
+            SystemInstruction::CreateAccount {
+                lamports: info_u64(info, "lamports")?,
+                space: info_u64(info, "space")?,
+                owner: info_pubkey(info, "owner")?,
+            },
+            accounts(&["source", "newAccount"])?,
+        ),
+        "assign" => (
+            SystemInstruction::Assign {
+                owner: info_pubkey(info, "owner")?,
+            },
+            accounts(&["account"])?,
+        ),
+        "transfer" => (
+            SystemInstruction::Transfer {
+                lamports: info_u64(info, "lamports")?,
+            },
+            accounts(&["source", "destination"])?,
+        ),
+        "createAccountWithSeed" => (
+            SystemInstruction::CreateAccountWithSeed {
+                base: info_pubkey(info, "base")?,
+                seed: info_str(info, "seed")?.to_string(),
+                lamports: info_u64(info, "lamports")?,
+                space: info_u64(info, "space")?,
+                owner: info_pubkey(info, "owner")?,
+            },
+            accounts(&["source", "newAccount"])?,
+        ),
+        "advanceNonce" => (
+            SystemInstruction::AdvanceNonceAccount,
+            accounts(&["nonceAccount", "recentBlockhashesSysvar", "nonceAuthority"])?,
+        ),
+        "withdrawFromNonce" => (
+            SystemInstruction::WithdrawNonceAccount(info_u64(info, "lamports")?),
+            accounts(&[
+                "nonceAccount",
+                "destination",
+                "recentBlockhashesSysvar",
+                "rentSysvar",
+                "nonceAuthority",
+            ])?,
+        ),
+        "initializeNonce" => (
+            SystemInstruction::InitializeNonceAccount(info_pubkey(info, "nonceAuthority")?),
+            accounts(&["nonceAccount", "recentBlockhashesSysvar", "rentSysvar"])?,
+        ),
+        "authorizeNonce" => (
+            SystemInstruction::AuthorizeNonceAccount(info_pubkey(info, "newAuthorized")?),
+            accounts(&["nonceAccount", "nonceAuthority"])?,
+        ),
+        "upgradeNonce" => (
+            SystemInstruction::UpgradeNonceAccount,
+            accounts(&["nonceAccount"])?,
+        ),
+        "allocate" => (
+            SystemInstruction::Allocate {
+                space: info_u64(info, "space")?,
+            },
+            accounts(&["account"])?,
+        ),
+        "allocateWithSeed" => (
+            SystemInstruction::AllocateWithSeed {
+                base: info_pubkey(info, "base")?,
+                seed: info_str(info, "seed")?.to_string(),
+                space: info_u64(info, "space")?,
+                owner: info_pubkey(info, "owner")?,
+            },
+            accounts(&["account"])?,
+        ),
+        "assignWithSeed" => (
+            SystemInstruction::AssignWithSeed {
+                base: info_pubkey(info, "base")?,
+                seed: info_str(info, "seed")?.to_string(),
+                owner: info_pubkey(info, "owner")?,
+            },
+            accounts(&["account"])?,
+        ),
+        "transferWithSeed" => (
+            SystemInstruction::TransferWithSeed {
+                lamports: info_u64(info, "lamports")?,
+                from_seed: info_str(info, "sourceSeed")?.to_string(),
+                from_owner: info_pubkey(info, "sourceOwner")?,
+            },
+            accounts(&["source", "sourceBase", "destination"])?,
+        ),
+        other => return Err(anyhow!("Unknown instruction type {other}")),
+    };
+    Ok(rebuilt)
+}
+
+fn info_str<'a>(info: &'a Value, name: &str) -> Result<&'a str> {
+    info[name]
+        .as_str()
+        .ok_or_else(|| anyhow!("Missing string field {name}"))
+}
+
+fn info_u64(info: &Value, name: &str) -> Result<u64> {
+    info[name]
+        .as_u64()
+        .ok_or_else(|| anyhow!("Missing integer field {name}"))
+}
+
+fn info_pubkey(info: &Value, name: &str) -> Result<Pubkey> {
+    info_str(info, name)?
+        .parse()
+        .with_context(|| format!("Invalid pubkey in field {name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_message::{compiled_instruction::CompiledInstruction, Message, VersionedMessage},
+        solana_sdk::{
+            instruction::{AccountMeta, Instruction},
+            signature::Signature,
+            transaction::VersionedTransaction,
+        },
+        solana_transaction_status::{
+            InnerInstruction, InnerInstructions, TransactionStatusMeta,
+            VersionedTransactionWithStatusMeta,
+        },
+    };
+
+    const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
+    /// A System transfer, followed by an instruction of `program_id` signed by the payer, with
+    /// the transfer repeated as an inner instruction of the second one.
+    fn transaction(program_id: Pubkey) -> VersionedTransactionWithStatusMeta {
+        let payer = Pubkey::new_unique();
+        let transfer = Instruction::new_with_bincode(
+            system_program::id(),
+            &SystemInstruction::Transfer { lamports: 42 },
+            vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new(Pubkey::new_unique(), false),
+            ],
+        );
+        let other = Instruction::new_with_bytes(
+            program_id,
+            b"hello",
+            vec![AccountMeta::new_readonly(payer, true)],
+        );
+        let message = Message::new(&[transfer, other], Some(&payer));
+        let inner_transfer: CompiledInstruction = message.instructions[0].clone();
+        VersionedTransactionWithStatusMeta {
+            transaction: VersionedTransaction {
+                signatures: vec![Signature::default()],
+                message: VersionedMessage::Legacy(message),
+            },
+            meta: TransactionStatusMeta {
+                inner_instructions: Some(vec![InnerInstructions {
+                    index: 1,
+                    instructions: vec![InnerInstruction {
+                        instruction: inner_transfer,
+                        stack_height: Some(2),
+                    }],
+                }]),
+                ..TransactionStatusMeta::default()
+            },
+        }
+    }
+
+    fn encode(
+        transaction: &VersionedTransactionWithStatusMeta,
+        encoding: UiTransactionEncoding,
+    ) -> EncodedTransactionWithStatusMeta {
+        let encoded = transaction.clone().encode(encoding, Some(0), true).unwrap();
+        serde_json::from_value(serde_json::to_value(encoded).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_json_parsed_is_rebuilt_as_json() {
+        let transaction = transaction(Pubkey::new_unique());
+        let json = encode(&transaction, UiTransactionEncoding::Json);
+        let mut parsed = encode(&transaction, UiTransactionEncoding::JsonParsed);
+        assert_eq!(
+            transaction_encoding(&parsed.transaction).unwrap(),
+            UiTransactionEncoding::JsonParsed
+        );
+
+        let encoding = normalize_transaction(&mut parsed).unwrap();
+        assert_eq!(encoding, UiTransactionEncoding::Json);
+        assert_eq!(parsed.transaction, json.transaction);
+        let (parsed_meta, json_meta) = (parsed.meta.unwrap(), json.meta.unwrap());
+        assert_eq!(parsed_meta.inner_instructions, json_meta.inner_instructions);
+        assert_eq!(parsed_meta.loaded_addresses, json_meta.loaded_addresses);
+    }
+
+    #[test]
+    fn test_json_parsed_instructions_of_other_programs_are_rejected() {
+        let transaction = transaction(MEMO_PROGRAM_ID.parse().unwrap());
+        let mut parsed = encode(&transaction, UiTransactionEncoding::JsonParsed);
+        let error = normalize_transaction(&mut parsed).unwrap_err();
+        assert!(
+            format!("{error:#}").contains("parsed spl-memo instruction"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn test_base58_is_reencoded_as_base64() {
+        let transaction = transaction(Pubkey::new_unique());
+        let base64 = encode(&transaction, UiTransactionEncoding::Base64);
+        let mut base58 = encode(&transaction, UiTransactionEncoding::Base58);
+        let encoding = normalize_transaction(&mut base58).unwrap();
+        assert_eq!(encoding, UiTransactionEncoding::Base64);
+        assert_eq!(base58.transaction, base64.transaction);
+    }
+}